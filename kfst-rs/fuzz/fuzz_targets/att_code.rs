@@ -0,0 +1,20 @@
+#![no_main]
+
+use kfst_rs::{ArbitraryAttRow, FST};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|rows: Vec<ArbitraryAttRow>| {
+    let att_code = rows
+        .into_iter()
+        .map(|row| row.0)
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    // Must never panic, no matter how malformed `att_code` is; Err is a perfectly fine outcome.
+    let parsed = FST::from_att_code(att_code, false);
+
+    // Anything that does parse should round-trip through to_att_code without panicking either.
+    if let Ok(fst) = parsed {
+        let _ = FST::from_att_code(fst.to_att_code(), false);
+    }
+});
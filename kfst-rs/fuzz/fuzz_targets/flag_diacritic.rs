@@ -0,0 +1,10 @@
+#![no_main]
+
+use kfst_rs::{ArbitraryFlagDiacriticString, FlagDiacriticSymbol};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|symbol: ArbitraryFlagDiacriticString| {
+    // Must never panic, even on a deliberately invalid flag type, a missing terminator, or a
+    // wildly oversized string; Err is a perfectly fine outcome.
+    let _ = FlagDiacriticSymbol::parse(&symbol.0);
+});
@@ -0,0 +1,11 @@
+#![no_main]
+
+use kfst_rs::{ArbitraryKfstBytes, FST};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|bytes: ArbitraryKfstBytes| {
+    // Must never panic on arbitrary (even well-formed-looking) bytes; Err is a perfectly fine
+    // outcome for data that isn't actually a valid transducer.
+    let _ = FST::from_kfst_bytes(&bytes.0, false);
+    let _ = FST::from_kfst_bytes_borrowed(&bytes.0);
+});
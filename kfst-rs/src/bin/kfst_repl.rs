@@ -0,0 +1,52 @@
+/*
+ This file is part of KFST.
+
+ (c) 2023-2025 Iikka Hauhio <iikka.hauhio@helsinki.fi> and Théo Salmenkivi-Friberg <theo.friberg@helsinki.fi>
+
+ KFST is free software: you can redistribute it and/or modify it under the
+ terms of the GNU Lesser General Public License as published by the Free
+ Software Foundation, either version 3 of the License, or (at your option) any
+ later version.
+
+ KFST is distributed in the hope that it will be useful, but WITHOUT ANY
+ WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more
+ details.
+
+ You should have received a copy of the GNU Lesser General Public License
+ along with KFST. If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Thin entry point for [kfst_rs::run_repl]: loads a transducer given on the command line and
+//! hands it, along with stdin/stdout, to the REPL. Build and run with:
+//! `cargo run --features repl --bin kfst_repl -- path/to/transducer.att`
+
+use std::io::{self, BufRead};
+
+use kfst_rs::{run_repl, FST};
+
+fn main() {
+    let path = match std::env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: kfst_repl <transducer.att|transducer.kfst>");
+            std::process::exit(1);
+        }
+    };
+
+    let fst = if path.ends_with(".kfst") {
+        FST::from_kfst_file(path, false)
+    } else {
+        FST::from_att_file(path, false)
+    };
+
+    let fst = match fst {
+        Ok(fst) => fst,
+        Err(err) => {
+            eprintln!("Failed to load transducer: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    run_repl(&fst, io::stdin().lock(), io::stdout()).unwrap();
+}
@@ -0,0 +1,174 @@
+/*
+ This file is part of KFST.
+
+ (c) 2023-2025 Iikka Hauhio <iikka.hauhio@helsinki.fi> and Théo Salmenkivi-Friberg <theo.friberg@helsinki.fi>
+
+ KFST is free software: you can redistribute it and/or modify it under the
+ terms of the GNU Lesser General Public License as published by the Free
+ Software Foundation, either version 3 of the License, or (at your option) any
+ later version.
+
+ KFST is distributed in the hope that it will be useful, but WITHOUT ANY
+ WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more
+ details.
+
+ You should have received a copy of the GNU Lesser General Public License
+ along with KFST. If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Structured, position-aware parse errors for the ATT/KFST loaders and the symbol parsers.
+//!
+//! Before this module existed, a malformed Voikko/Omorfi transducer just produced an opaque
+//! `value_error`/`io_error` string. [FstParseError] instead carries the byte offset, the
+//! (1-based) line/column it maps to, the offending token text and an [FstParseErrorKind] so
+//! callers - Python or Rust - can tell what actually went wrong and where.
+
+#[cfg(feature = "python")]
+use pyo3::create_exception;
+
+/// What kind of thing went wrong while parsing ATT/KFST data or a symbol's string form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FstParseErrorKind {
+    /// A `@X.Y@`/`@X.Y.Z@`-shaped token was not a valid [crate::FlagDiacriticSymbol].
+    BadFlagDiacritic,
+    /// A line of ATT code had a column count or field that didn't parse (see [crate::FST::from_att_code]).
+    MalformedAttRow,
+    /// The `"KFST"` magic/version/metadata header was missing or cut short (see [crate::FST::from_kfst_bytes]).
+    TruncatedKfstHeader,
+    /// A transition referenced a symbol-table index that doesn't exist, or a symbol was parsed
+    /// that doesn't round-trip to the same text (see `Symbol::parse`).
+    SymbolTableMismatch,
+}
+
+impl FstParseErrorKind {
+    fn description(&self) -> &'static str {
+        match self {
+            FstParseErrorKind::BadFlagDiacritic => "bad flag diacritic",
+            FstParseErrorKind::MalformedAttRow => "malformed ATT row",
+            FstParseErrorKind::TruncatedKfstHeader => "truncated KFST header",
+            FstParseErrorKind::SymbolTableMismatch => "symbol-table mismatch",
+        }
+    }
+}
+
+/// A parse failure with enough context to point a user (or an editor) at the offending text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FstParseError {
+    /// What kind of failure this is.
+    pub kind: FstParseErrorKind,
+    /// 0-based byte offset into the original input at which the failure was detected.
+    pub offset: usize,
+    /// 1-based line number corresponding to [FstParseError::offset].
+    pub line: usize,
+    /// 1-based column number (in bytes) corresponding to [FstParseError::offset].
+    pub column: usize,
+    /// The offending token or line text, for display purposes.
+    pub token: String,
+}
+
+impl FstParseError {
+    /// Build an [FstParseError], computing `line`/`column` by scanning `source` up to `offset`.
+    pub fn at(source: &str, offset: usize, token: impl Into<String>, kind: FstParseErrorKind) -> Self {
+        let mut boundary = offset.min(source.len());
+        while boundary > 0 && !source.is_char_boundary(boundary) {
+            boundary -= 1;
+        }
+        let prefix = &source[..boundary];
+        let line = prefix.bytes().filter(|&b| b == b'\n').count() + 1;
+        let column = match prefix.rfind('\n') {
+            Some(last_newline) => offset - last_newline,
+            None => offset + 1,
+        };
+        FstParseError {
+            kind,
+            offset,
+            line,
+            column,
+            token: token.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for FstParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at line {}, column {} (byte offset {}): {:?}",
+            self.kind.description(),
+            self.line,
+            self.column,
+            self.offset,
+            self.token
+        )
+    }
+}
+
+impl std::error::Error for FstParseError {}
+
+/// The error type returned by the non-Python build of the ATT/KFST loaders: either a structured
+/// [FstParseError] or an opaque message-only failure that doesn't have a meaningful position to
+/// report.
+#[cfg(not(feature = "python"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KfstError {
+    /// A failure carried only as a message: reading or writing a file, an invalid symbol or
+    /// flag diacritic type passed to a constructor, a failed serialization, or input that
+    /// cannot be split into symbols.
+    Io(String),
+    /// Failed to parse ATT/KFST data or a symbol; see [FstParseError].
+    Parse(FstParseError),
+}
+
+#[cfg(not(feature = "python"))]
+impl std::fmt::Display for KfstError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KfstError::Io(msg) => write!(f, "{}", msg),
+            KfstError::Parse(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+#[cfg(not(feature = "python"))]
+impl std::error::Error for KfstError {}
+
+#[cfg(feature = "python")]
+create_exception!(
+    kfst_rs,
+    KfstError,
+    pyo3::exceptions::PyException,
+    "Base class for all structured KFST parse errors. Carries no fields of its own; match on one\nof its subclasses (or call `str()` on the instance) to see the offset/line/column/token."
+);
+
+#[cfg(feature = "python")]
+create_exception!(
+    kfst_rs,
+    BadFlagDiacriticError,
+    KfstError,
+    "Raised when a `@X.Y@`/`@X.Y.Z@`-shaped token is not a valid flag diacritic."
+);
+
+#[cfg(feature = "python")]
+create_exception!(
+    kfst_rs,
+    MalformedAttRowError,
+    KfstError,
+    "Raised when a line of AT&T-format transducer code does not have a valid shape."
+);
+
+#[cfg(feature = "python")]
+create_exception!(
+    kfst_rs,
+    TruncatedKfstHeaderError,
+    KfstError,
+    "Raised when a KFST binary blob is missing or has a truncated `\"KFST\"` header."
+);
+
+#[cfg(feature = "python")]
+create_exception!(
+    kfst_rs,
+    SymbolTableMismatchError,
+    KfstError,
+    "Raised when a transition references a symbol-table index that does not exist."
+);
@@ -0,0 +1,145 @@
+/*
+ This file is part of KFST.
+
+ (c) 2023-2025 Iikka Hauhio <iikka.hauhio@helsinki.fi> and Théo Salmenkivi-Friberg <theo.friberg@helsinki.fi>
+
+ KFST is free software: you can redistribute it and/or modify it under the
+ terms of the GNU Lesser General Public License as published by the Free
+ Software Foundation, either version 3 of the License, or (at your option) any
+ later version.
+
+ KFST is distributed in the hope that it will be useful, but WITHOUT ANY
+ WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more
+ details.
+
+ You should have received a copy of the GNU Lesser General Public License
+ along with KFST. If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Structured [arbitrary] generators for the fuzz targets in `fuzz/`, gated behind the `fuzz`
+//! feature. Raw random bytes almost never exercise the interesting code paths of a binary format
+//! with magic bytes and an lzma-compressed payload, or of a tab-delimited text format with a
+//! fixed column count - these newtypes build plausible-shaped-but-varied inputs instead, while
+//! still letting the fuzzer mutate the pieces (states, symbols, lengths) freely.
+
+use arbitrary::{Arbitrary, Unstructured};
+
+/// A plausible-but-arbitrary single line of AT&T-format transducer code: either a final-state row
+/// (`state[\tweight]`) or a transition row (`state1\tstate2\tsym1\tsym2[\tweight]`). See
+/// [crate::FST::from_att_code] for the format this is meant to stress.
+#[derive(Debug, Clone)]
+pub struct ArbitraryAttRow(pub String);
+
+impl<'a> Arbitrary<'a> for ArbitraryAttRow {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        const SYMBOLS: [&str; 7] = [
+            "a",
+            "b",
+            "@U.X.Y@",
+            "@_EPSILON_SYMBOL_@",
+            "@_UNKNOWN_SYMBOL_@",
+            "\t",
+            "@",
+        ];
+        let pick_symbol = |u: &mut Unstructured<'a>| -> arbitrary::Result<&'static str> {
+            Ok(SYMBOLS[u.int_in_range(0..=SYMBOLS.len() - 1)?])
+        };
+
+        if bool::arbitrary(u)? {
+            let state = u64::arbitrary(u)?;
+            if bool::arbitrary(u)? {
+                let weight = f64::arbitrary(u)?;
+                Ok(ArbitraryAttRow(format!("{}\t{}", state, weight)))
+            } else {
+                Ok(ArbitraryAttRow(state.to_string()))
+            }
+        } else {
+            let state_1 = u64::arbitrary(u)?;
+            let state_2 = u64::arbitrary(u)?;
+            let symbol_1 = pick_symbol(u)?;
+            let symbol_2 = pick_symbol(u)?;
+            if bool::arbitrary(u)? {
+                let weight = f64::arbitrary(u)?;
+                Ok(ArbitraryAttRow(format!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    state_1, state_2, symbol_1, symbol_2, weight
+                )))
+            } else {
+                Ok(ArbitraryAttRow(format!(
+                    "{}\t{}\t{}\t{}",
+                    state_1, state_2, symbol_1, symbol_2
+                )))
+            }
+        }
+    }
+}
+
+/// A plausible-but-arbitrary flag diacritic string of the form `@X.Y.Z@` or `@X.Y@`, occasionally
+/// with a deliberately invalid flag type or a missing terminator so the error paths get exercised
+/// too. See [crate::FlagDiacriticSymbol::parse].
+#[derive(Debug, Clone)]
+pub struct ArbitraryFlagDiacriticString(pub String);
+
+impl<'a> Arbitrary<'a> for ArbitraryFlagDiacriticString {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        // "Q" is not a valid flag type; kept in the mix deliberately.
+        const FLAG_TYPES: [&str; 7] = ["U", "R", "D", "C", "P", "N", "Q"];
+        let flag_type = FLAG_TYPES[u.int_in_range(0..=FLAG_TYPES.len() - 1)?];
+        let clean = |s: String| -> String { s.chars().filter(|c| *c != '.' && *c != '@').take(16).collect() };
+        let key = clean(String::arbitrary(u)?);
+        let value = bool::arbitrary(u)?
+            .then(|| String::arbitrary(u))
+            .transpose()?
+            .map(clean);
+
+        let body = match value {
+            Some(value) => format!("@{}.{}.{}", flag_type, key, value),
+            None => format!("@{}.{}", flag_type, key),
+        };
+        Ok(ArbitraryFlagDiacriticString(if bool::arbitrary(u)? {
+            format!("{}@", body)
+        } else {
+            body
+        }))
+    }
+}
+
+/// A plausible-but-arbitrary KFST binary blob: a well-formed `"KFST"` header and symbol table
+/// wrapped around an arbitrary payload (not necessarily validly lzma-compressed), so the fuzzer
+/// spends most of its time past the header instead of getting rejected by the magic-byte check.
+/// See [crate::FST::from_kfst_bytes].
+#[derive(Debug, Clone)]
+pub struct ArbitraryKfstBytes(pub Vec<u8>);
+
+impl<'a> Arbitrary<'a> for ArbitraryKfstBytes {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"KFST");
+        bytes.extend_from_slice(&0u16.to_be_bytes()); // version
+
+        let num_symbols: u16 = u.int_in_range(0..=8)?;
+        let num_transitions: u32 = u.int_in_range(0..=8)?;
+        let num_final_states: u32 = u.int_in_range(0..=8)?;
+        let is_weighted = u8::arbitrary(u)? & 1;
+
+        bytes.extend_from_slice(&num_symbols.to_be_bytes());
+        bytes.extend_from_slice(&num_transitions.to_be_bytes());
+        bytes.extend_from_slice(&num_final_states.to_be_bytes());
+        bytes.push(is_weighted);
+
+        for _ in 0..num_symbols {
+            let len = u.int_in_range(0..=3)?;
+            for _ in 0..len {
+                bytes.push(if bool::arbitrary(u)? { b'a' } else { b'b' });
+            }
+            bytes.push(b'\0');
+        }
+
+        // Past this point the real format is lzma-compressed; leaving the remainder arbitrary
+        // lets the fuzzer explore the decompression-failure path as well as malformed payloads.
+        bytes.extend_from_slice(Vec::<u8>::arbitrary(u)?.as_slice());
+
+        Ok(ArbitraryKfstBytes(bytes))
+    }
+}
@@ -73,19 +73,22 @@ use std::collections::HashSet;
 use std::fmt::Debug;
 #[cfg(feature = "python")]
 use std::fmt::Error;
+use std::borrow::Cow;
 use std::fs::{self, File};
 use std::hash::Hash;
-use std::io::Read;
+use std::io::{BufRead, Read, Write};
 use std::path::Path;
 
 use im::HashMap;
 use indexmap::{indexmap, IndexMap, IndexSet};
-use lzma_rs::lzma_compress;
 use nom::branch::alt;
 use nom::bytes::complete::{tag, take_until1};
 use nom::multi::many_m_n;
 use nom::Parser;
-use std::sync::{LazyLock, Mutex};
+use std::sync::{LazyLock, OnceLock, RwLock};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "python")]
 use pyo3::create_exception;
@@ -96,6 +99,41 @@ use pyo3::types::PyDict;
 #[cfg(feature = "python")]
 use pyo3::{prelude::*, py_run, IntoPyObjectExt};
 
+mod error;
+pub use error::{FstParseError, FstParseErrorKind};
+#[cfg(not(feature = "python"))]
+pub use error::KfstError;
+#[cfg(feature = "python")]
+pub use error::{
+    BadFlagDiacriticError, KfstError, MalformedAttRowError, SymbolTableMismatchError,
+    TruncatedKfstHeaderError,
+};
+
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!();
+#[cfg(feature = "uniffi")]
+mod uniffi_api;
+#[cfg(feature = "uniffi")]
+pub use uniffi_api::{
+    LookupResult, UniffiError, UniffiFlagDiacriticSymbol, UniffiFlagEntry, UniffiFst,
+    UniffiFstState, UniffiParseErrorKind, UniffiStringSymbol, UniffiSymbol,
+};
+
+#[cfg(feature = "fuzz")]
+mod fuzz_gen;
+#[cfg(feature = "fuzz")]
+pub use fuzz_gen::{ArbitraryAttRow, ArbitraryFlagDiacriticString, ArbitraryKfstBytes};
+
+#[cfg(feature = "repl")]
+mod repl;
+#[cfg(feature = "repl")]
+pub use repl::run_repl;
+
+#[cfg(feature = "mmap")]
+mod mmap;
+#[cfg(feature = "mmap")]
+pub use mmap::mmap_kfst_file;
+
 // We have result types that kinda depend on the target
 // If we target pyo3, we want python results and errors
 // Otherwise, we want stdlib errors
@@ -103,7 +141,7 @@ use pyo3::{prelude::*, py_run, IntoPyObjectExt};
 #[cfg(feature = "python")]
 type KFSTResult<T> = PyResult<T>;
 #[cfg(not(feature = "python"))]
-type KFSTResult<T> = std::result::Result<T, String>;
+type KFSTResult<T> = std::result::Result<T, KfstError>;
 
 #[cfg(feature = "python")]
 fn value_error<T>(msg: String) -> KFSTResult<T> {
@@ -111,7 +149,7 @@ fn value_error<T>(msg: String) -> KFSTResult<T> {
 }
 #[cfg(not(feature = "python"))]
 fn value_error<T>(msg: String) -> KFSTResult<T> {
-    KFSTResult::Err(msg)
+    KFSTResult::Err(KfstError::Io(msg))
 }
 
 #[cfg(feature = "python")]
@@ -122,7 +160,32 @@ fn io_error<T>(msg: String) -> KFSTResult<T> {
 }
 #[cfg(not(feature = "python"))]
 fn io_error<T>(msg: String) -> KFSTResult<T> {
-    KFSTResult::Err(msg)
+    KFSTResult::Err(KfstError::Io(msg))
+}
+
+/// Turn a structured [FstParseError] into the appropriate [KFSTResult] error: a matching
+/// `KfstError` subclass (see [error]) when built with the `python` feature, or the
+/// [FstParseError] itself (wrapped in [KfstError::Parse]) otherwise, so non-Python callers can
+/// match on [FstParseError::kind] instead of parsing a message string.
+fn parse_error<T>(err: FstParseError) -> KFSTResult<T> {
+    #[cfg(feature = "python")]
+    {
+        let msg = err.to_string();
+        KFSTResult::Err(match err.kind {
+            FstParseErrorKind::BadFlagDiacritic => PyErr::new::<BadFlagDiacriticError, _>(msg),
+            FstParseErrorKind::MalformedAttRow => PyErr::new::<MalformedAttRowError, _>(msg),
+            FstParseErrorKind::TruncatedKfstHeader => {
+                PyErr::new::<TruncatedKfstHeaderError, _>(msg)
+            }
+            FstParseErrorKind::SymbolTableMismatch => {
+                PyErr::new::<SymbolTableMismatchError, _>(msg)
+            }
+        })
+    }
+    #[cfg(not(feature = "python"))]
+    {
+        KFSTResult::Err(KfstError::Parse(err))
+    }
 }
 
 #[cfg(feature = "python")]
@@ -131,7 +194,7 @@ fn tokenization_exception<T>(msg: String) -> KFSTResult<T> {
 }
 #[cfg(not(feature = "python"))]
 fn tokenization_exception<T>(msg: String) -> KFSTResult<T> {
-    KFSTResult::Err(msg)
+    KFSTResult::Err(KfstError::Io(msg))
 }
 
 #[cfg(feature = "python")]
@@ -142,21 +205,67 @@ create_exception!(
 );
 
 // Symbol interning
+//
+// The interner is sharded so that free-threaded builds (no GIL serializing callers) don't
+// serialize every `intern`/`deintern` call on one global lock. A symbol's string is hashed to
+// pick one of `INTERNER_SHARDS` independent `RwLock<IndexSet<Box<str>>>` buckets; the returned
+// `u32` packs the shard index into the top `SHARD_BITS` bits and the index within that shard's
+// set into the rest, so `deintern` can route straight back to the right shard without knowing
+// anything else about where the symbol came from. Strings live in their shard's `IndexSet` for
+// the remainder of the program (matching the previous single-lock interner's lifetime contract);
+// they're stored as `Box<str>` rather than `String` as a reminder that nothing should grow them
+// in place.
+
+const INTERNER_SHARDS: usize = 16;
+const SHARD_BITS: u32 = INTERNER_SHARDS.ilog2();
+const LOCAL_INDEX_BITS: u32 = u32::BITS - SHARD_BITS;
+const LOCAL_INDEX_MASK: u32 = (1u32 << LOCAL_INDEX_BITS) - 1;
+
+static STRING_INTERNER: LazyLock<[RwLock<IndexSet<Box<str>>>; INTERNER_SHARDS]> =
+    LazyLock::new(|| std::array::from_fn(|_| RwLock::new(IndexSet::new())));
+
+fn shard_of(s: &str) -> usize {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    s.hash(&mut hasher);
+    (hasher.finish() as usize) & (INTERNER_SHARDS - 1)
+}
+
+fn pack_symbol_index(shard: usize, local_index: usize) -> u32 {
+    let local_index = u32::try_from(local_index).unwrap_or(LOCAL_INDEX_MASK);
+    ((shard as u32) << LOCAL_INDEX_BITS) | (local_index & LOCAL_INDEX_MASK)
+}
 
-static STRING_INTERNER: LazyLock<Mutex<IndexSet<String>>> =
-    LazyLock::new(|| Mutex::new(IndexSet::new()));
+fn unpack_symbol_index(idx: u32) -> (usize, usize) {
+    ((idx >> LOCAL_INDEX_BITS) as usize, (idx & LOCAL_INDEX_MASK) as usize)
+}
 
 fn intern(s: String) -> u32 {
-    u32::try_from(STRING_INTERNER.lock().unwrap().insert_full(s).0).unwrap()
+    let shard = shard_of(&s);
+    {
+        let bucket = STRING_INTERNER[shard].read().unwrap();
+        if let Some(local_index) = bucket.get_index_of(s.as_str()) {
+            return pack_symbol_index(shard, local_index);
+        }
+    }
+    // Miss under the read lock: upgrade to a write lock, but re-check first in case another
+    // thread interned the same string while we didn't hold any lock at all.
+    let mut bucket = STRING_INTERNER[shard].write().unwrap();
+    if let Some(local_index) = bucket.get_index_of(s.as_str()) {
+        return pack_symbol_index(shard, local_index);
+    }
+    let (local_index, _) = bucket.insert_full(s.into_boxed_str());
+    pack_symbol_index(shard, local_index)
 }
 
 fn deintern(idx: u32) -> String {
-    STRING_INTERNER
-        .lock()
+    let (shard, local_index) = unpack_symbol_index(idx);
+    STRING_INTERNER[shard]
+        .read()
         .unwrap()
-        .get_index(idx.try_into().unwrap())
+        .get_index(local_index)
         .unwrap()
-        .clone()
+        .to_string()
 }
 
 #[cfg_attr(
@@ -568,6 +677,7 @@ impl StringSymbol {
 }
 
 #[cfg_attr(feature = "python", pyclass(eq, ord, frozen))]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy, Hash)]
 /// The different types of flag diacritic supported by kfst_rs.
 pub enum FlagDiacriticType {
@@ -650,8 +760,20 @@ impl Ord for FlagDiacriticSymbol {
 }
 
 impl FlagDiacriticSymbol {
+    /// A real flag diacritic is a handful of bytes (`@X.Y.Z@`); anything wildly longer than this
+    /// is rejected up front rather than handed to `take_until1("@")`, which would otherwise scan
+    /// (and, on failure, get echoed back whole into an [FstParseError::token]) all the way to the
+    /// end of whatever string the caller passed in.
+    const MAX_LEN: usize = 1024;
+
     /// Parse a flag diacritic from a string representation of the form @SYMBOL_TYPE.KEY.VALUE@ or @SYMBOL_TYPE.KEY@.
     pub fn parse(symbol: &str) -> nom::IResult<&str, FlagDiacriticSymbol> {
+        if symbol.len() > FlagDiacriticSymbol::MAX_LEN {
+            return Err(nom::Err::Error(nom::error::Error::new(
+                symbol,
+                nom::error::ErrorKind::TooLarge,
+            )));
+        }
         let mut parser = (
             tag("@"),
             alt((tag("U"), tag("R"), tag("D"), tag("C"), tag("P"), tag("N"))),
@@ -695,9 +817,19 @@ impl FlagDiacriticSymbol {
 impl FlagDiacriticSymbol {
     fn _from_symbol_string(symbol: &str) -> KFSTResult<Self> {
         match FlagDiacriticSymbol::parse(symbol) {
-            Ok(("", symbol)) => KFSTResult::Ok(symbol),
-            Ok((rest, _)) => value_error(format!("String {:?} contains a valid FlagDiacriticSymbol, but it has unparseable text at the end: {:?}", symbol, rest)),
-            _ => value_error(format!("Not a valid FlagDiacriticSymbol: {:?}", symbol))
+            Ok(("", parsed)) => KFSTResult::Ok(parsed),
+            Ok((rest, _)) => parse_error(FstParseError::at(
+                symbol,
+                symbol.len() - rest.len(),
+                rest,
+                FstParseErrorKind::BadFlagDiacritic,
+            )),
+            _ => parse_error(FstParseError::at(
+                symbol,
+                0,
+                symbol,
+                FstParseErrorKind::BadFlagDiacritic,
+            )),
         }
     }
 
@@ -844,6 +976,7 @@ impl std::fmt::Debug for FlagDiacriticSymbol {
 }
 
 #[cfg_attr(feature = "python", pyclass(eq, ord, frozen, hash))]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
 #[derive(PartialEq, Eq, Clone, Hash, Copy)]
 /// The three possible HFST special symbols.
 pub enum SpecialSymbol {
@@ -899,14 +1032,17 @@ impl SpecialSymbol {
     /// Parse a special symbol from a text representation.
     ///
     /// ```rust
-    /// use kfst_rs::SpecialSymbol;
+    /// use kfst_rs::{KfstError, SpecialSymbol};
     ///
     /// assert_eq!(SpecialSymbol::from_symbol_string("@_EPSILON_SYMBOL_@"), Ok(SpecialSymbol::EPSILON));
     /// // Or alternatively
     /// assert_eq!(SpecialSymbol::from_symbol_string("@0@"), Ok(SpecialSymbol::EPSILON));
     /// assert_eq!(SpecialSymbol::from_symbol_string("@_IDENTITY_SYMBOL_@"), Ok(SpecialSymbol::IDENTITY));
     /// assert_eq!(SpecialSymbol::from_symbol_string("@_UNKNOWN_SYMBOL_@"), Ok(SpecialSymbol::UNKNOWN));
-    /// assert_eq!(SpecialSymbol::from_symbol_string("@_GARBAGE_SYMBOL_@"), Err("Not a valid SpecialSymbol: \"@_GARBAGE_SYMBOL_@\"".to_string()));
+    /// assert_eq!(
+    ///     SpecialSymbol::from_symbol_string("@_GARBAGE_SYMBOL_@"),
+    ///     Err(KfstError::Io("Not a valid SpecialSymbol: \"@_GARBAGE_SYMBOL_@\"".to_string()))
+    /// );
     /// ```
     pub fn from_symbol_string(symbol: &str) -> KFSTResult<Self> {
         SpecialSymbol::_from_symbol_string(symbol)
@@ -1174,16 +1310,86 @@ impl Symbol {
 
 #[cfg(feature = "python")]
 impl FromPyObject<'_> for Symbol {
+    /// Dispatches on the concrete Python type once, rather than trying (and discarding the
+    /// failure of) every variant's `extract` in turn: each of [SpecialSymbol], [FlagDiacriticSymbol],
+    /// [StringSymbol] and [RawSymbol] is a registered `#[pyclass]`, so a single `isinstance` check
+    /// picks the right branch directly. Anything else is accepted as an [Symbol::External] symbol
+    /// as long as it duck-types the is_epsilon/is_unknown/get_symbol protocol those objects are
+    /// expected to implement; an object that is none of the above gets one informative error
+    /// naming its type and listing the accepted classes, instead of the last (unrelated) extract
+    /// failure.
     fn extract_bound(ob: &Bound<'_, PyAny>) -> PyResult<Self> {
-        ob.extract()
-            .map(Symbol::Special)
-            .or_else(|_| ob.extract().map(Symbol::Flag))
-            .or_else(|_| ob.extract().map(Symbol::String))
-            .or_else(|_| ob.extract().map(Symbol::External))
-            .or_else(|_| ob.extract().map(Symbol::Raw))
+        if ob.is_instance_of::<SpecialSymbol>() {
+            return ob.extract().map(Symbol::Special);
+        }
+        if ob.is_instance_of::<FlagDiacriticSymbol>() {
+            return ob.extract().map(Symbol::Flag);
+        }
+        if ob.is_instance_of::<StringSymbol>() {
+            return ob.extract().map(Symbol::String);
+        }
+        if ob.is_instance_of::<RawSymbol>() {
+            return ob.extract().map(Symbol::Raw);
+        }
+        if ob.hasattr("is_epsilon")? && ob.hasattr("is_unknown")? && ob.hasattr("get_symbol")? {
+            return ob.extract().map(Symbol::External);
+        }
+        Err(PyErr::new::<pyo3::exceptions::PyTypeError, _>(format!(
+            "{} is not a valid symbol: expected a SpecialSymbol, FlagDiacriticSymbol, \
+             StringSymbol or RawSymbol, or an object implementing is_epsilon, is_unknown and \
+             get_symbol",
+            ob.get_type()
+        )))
+    }
+}
+
+#[cfg(feature = "serde")]
+/// Serde-only shadow of [Symbol]: [Symbol::Special], [Symbol::Flag] and [Symbol::String] all
+/// round-trip losslessly through [Symbol::get_symbol] and [Symbol::parse], so they share a single
+/// `Text` representation; [Symbol::Raw]'s `get_symbol()` is a debug dump rather than a parseable
+/// form, so its 15 bytes are stored directly instead.
+#[derive(Serialize, Deserialize)]
+enum SymbolRepr {
+    Text(String),
+    Raw([u8; 15]),
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Symbol {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let repr = match self {
+            Symbol::Raw(raw_symbol) => SymbolRepr::Raw(raw_symbol.value),
+            #[cfg(feature = "python")]
+            Symbol::External(_) => {
+                return Err(serde::ser::Error::custom(
+                    "Symbol::External wraps a Python object and cannot be serialized",
+                ))
+            }
+            symbol => SymbolRepr::Text(symbol.get_symbol()),
+        };
+        repr.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        match SymbolRepr::deserialize(deserializer)? {
+            SymbolRepr::Raw(value) => Ok(Symbol::Raw(RawSymbol { value })),
+            SymbolRepr::Text(text) => Symbol::parse(&text).map(|(_, symbol)| symbol).map_err(|_| {
+                serde::de::Error::custom(format!("{:?} is not a valid symbol", text))
+            }),
+        }
     }
 }
-#[derive(Clone, Debug, PartialEq, Hash)]
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 #[readonly::make]
 /// The flag state of an [FSTState]:
 /// ```no_test
@@ -1220,9 +1426,54 @@ impl<'py> IntoPyObject<'py> for FlagMap {
     }
 }
 
+#[cfg(feature = "serde")]
+impl Serialize for FlagMap {
+    /// Serializes as `name -> (positive, value)`, deinterning both `name` and `value`. A flag
+    /// diacritic parsed without a value (e.g. a bare `@R.KEY@`) deinterns to the empty string,
+    /// since the interned form's "no value" sentinel isn't a valid string index.
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let as_map: std::collections::BTreeMap<String, (bool, String)> = self
+            .0
+            .iter()
+            .map(|(&key, &(positive, value))| {
+                let value = if value == u32::MAX {
+                    String::new()
+                } else {
+                    deintern(value)
+                };
+                (deintern(key), (positive, value))
+            })
+            .collect();
+        as_map.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for FlagMap {
+    /// Reinterns `name`/`value` pairs produced by [FlagMap]'s [Serialize] impl. Note this can't
+    /// recover the "no value" sentinel: a deserialized flag's value is always interned, even if
+    /// it was serialized from the empty-string placeholder for "no value".
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let as_map = std::collections::BTreeMap::<String, (bool, String)>::deserialize(deserializer)?;
+        Ok(FlagMap(
+            as_map
+                .into_iter()
+                .map(|(key, (positive, value))| (intern(key), (positive, intern(value))))
+                .collect(),
+        ))
+    }
+}
+
 // transducer.py
 
 #[cfg_attr(feature = "python", pyclass(frozen, eq, hash, get_all))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Clone, Debug, PartialEq)]
 #[readonly::make]
 /// A state in an [FST].
@@ -1360,9 +1611,182 @@ impl FSTState {
             self.output_symbols
         )
     }
+
+    /// Apply a single flag diacritic to this state's input-side flags, implementing the full
+    /// HFST P/N/R/D/C/U semantics (see [FlagDiacriticType]). Returns [None] if `flag`'s condition
+    /// is not met, meaning the transition it is attached to may not be taken. On success, returns
+    /// a new state in which only [FSTState::input_flags] has changed, and only at `flag`'s key;
+    /// path weight, output flags and output symbols are left untouched.
+    pub fn apply_flag(&self, flag: &FlagDiacriticSymbol) -> Option<FSTState> {
+        let input_flags = _apply_flag_to_map(flag, &self.input_flags.0)?;
+        Some(FSTState {
+            input_flags: FlagMap(input_flags),
+            ..self.clone()
+        })
+    }
+}
+
+#[cfg_attr(feature = "python", pyclass(eq, frozen))]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+/// Which on-disk representation [FST::from_bytes]/[FST::from_file] detected.
+pub enum FstFormat {
+    /// Plain-text AT&T format (see [FST::from_att_code]).
+    Att,
+    /// Binary KFST format, recognised by its leading `"KFST"` magic tag (see [FST::from_kfst_bytes]).
+    Kfst,
+}
+
+#[cfg_attr(feature = "python", pyclass(eq, frozen))]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+/// Which codec compresses the transition-table payload of a KFST binary file, see
+/// [FST::to_kfst_bytes_with].
+pub enum Compression {
+    /// Compressed with [lzma_rs::xz_decompress]/[lzma_rs::xz_compress]. The default, and the only
+    /// codec a KFST file could use before this enum existed.
+    Xz,
+    /// Stored as-is, with no compression; useful for inspecting a dump by hand or trading disk
+    /// space for faster loading.
+    None,
+}
+
+/// Leading bytes of an xz stream ([lzma_rs::xz_decompress]'s container format), used to sniff
+/// whether a KFST payload is actually compressed regardless of what its codec byte claims (see
+/// [FST::_decompress_payload]).
+const XZ_MAGIC: [u8; 6] = [0xFD, 0x37, 0x7A, 0x58, 0x5A, 0x00];
+
+/// Magic tag at the start of a `kfstpack` container ([PackBuilder]/[FST::from_pack_bytes]),
+/// distinct from a bare KFST blob's `"KFST"` tag so the two never collide.
+const PACK_MAGIC: &[u8; 4] = b"KPAK";
+
+/// Tag byte of a `kfstpack` record carrying one named, embedded KFST blob: `u32` name length,
+/// the name itself, then the rest of the record is the KFST bytes verbatim.
+const PACK_RECORD_FST: u8 = b'F';
+
+/// Tag byte of a `kfstpack` record carrying the container's free-form metadata: `u32` pair
+/// count, then that many `(u32 key length, key, u32 value length, value)` tuples.
+const PACK_RECORD_METADATA: u8 = b'M';
+
+/// A weight algebra for combining the per-transition weights of an [FST]. [FST]/[FSTState]
+/// themselves stay hard-coded to plain `f64` tropical weights - they're `#[pyclass]`es, and pyo3
+/// classes and methods can't be generic - but [FST::lookup_semiring] uses this trait to let a
+/// Rust-only caller recompute the same traversal under a different algebra without [FST] itself
+/// needing to change.
+///
+/// Every raw weight stored in an [FST]'s transition table is a tropical-style cost (lower is
+/// better, as accumulated by plain `f64` addition and compared by [FST::lookup]); [Semiring::lift]
+/// is how an implementation interprets that stored cost as a value of `Self`. [Tropical] and
+/// [Log] interpret it directly; [Probability] interprets it as `exp(-cost)`, the usual duality
+/// between a tropical/log cost and the probability it represents.
+pub trait Semiring: Copy {
+    /// The identity element of [Semiring::plus] - combining any value with `zero()` leaves it
+    /// unchanged.
+    fn zero() -> Self;
+    /// The identity element of [Semiring::times] - combining any value with `one()` leaves it
+    /// unchanged. An empty path (no transitions taken) has weight `one()`.
+    fn one() -> Self;
+    /// Combines the weights of two alternative paths that produce the same output.
+    fn plus(self, other: Self) -> Self;
+    /// Extends a path's accumulated weight by one more transition's weight.
+    fn times(self, other: Self) -> Self;
+    /// Interprets one transition's (or final state's) raw stored weight as a value of `Self`.
+    fn lift(weight: f64) -> Self;
+}
+
+/// The semiring [FST::lookup]/[FST::run_fst] use natively: path weights accumulate by addition
+/// (`times`) and only the lowest-cost path to a given output survives (`plus = min`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tropical(pub f64);
+
+impl Semiring for Tropical {
+    fn zero() -> Self {
+        Tropical(f64::INFINITY)
+    }
+
+    fn one() -> Self {
+        Tropical(0.0)
+    }
+
+    fn plus(self, other: Self) -> Self {
+        Tropical(self.0.min(other.0))
+    }
+
+    fn times(self, other: Self) -> Self {
+        Tropical(self.0 + other.0)
+    }
+
+    fn lift(weight: f64) -> Self {
+        Tropical(weight)
+    }
+}
+
+/// Like [Tropical], but `plus` combines alternative paths by log-sum-exp (`-ln(e^-a + e^-b)`)
+/// instead of taking the minimum, so the result is the *soft* minimum cost over every accepting
+/// path to a given output rather than just the cheapest one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Log(pub f64);
+
+impl Semiring for Log {
+    fn zero() -> Self {
+        Log(f64::INFINITY)
+    }
+
+    fn one() -> Self {
+        Log(0.0)
+    }
+
+    fn plus(self, other: Self) -> Self {
+        // e^-inf is 0, so either operand being zero() must short-circuit to the other: the exact
+        // arithmetic below would otherwise compute inf - inf = NaN.
+        if self.0.is_infinite() {
+            return other;
+        }
+        if other.0.is_infinite() {
+            return self;
+        }
+        let smallest = self.0.min(other.0);
+        Log(smallest - (f64::exp(smallest - self.0) + f64::exp(smallest - other.0)).ln())
+    }
+
+    fn times(self, other: Self) -> Self {
+        Log(self.0 + other.0)
+    }
+
+    fn lift(weight: f64) -> Self {
+        Log(weight)
+    }
+}
+
+/// The true probability mass of each output: a stored tropical cost `w` is read as the
+/// probability `e^-w`, `times` multiplies probabilities along a path, and `plus` sums the
+/// probabilities of every accepting path that produces the same output.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Probability(pub f64);
+
+impl Semiring for Probability {
+    fn zero() -> Self {
+        Probability(0.0)
+    }
+
+    fn one() -> Self {
+        Probability(1.0)
+    }
+
+    fn plus(self, other: Self) -> Self {
+        Probability(self.0 + other.0)
+    }
+
+    fn times(self, other: Self) -> Self {
+        Probability(self.0 * other.0)
+    }
+
+    fn lift(weight: f64) -> Self {
+        Probability((-weight).exp())
+    }
 }
 
-#[cfg_attr(feature = "python", pyclass(frozen, get_all))]
+#[cfg_attr(feature = "python", pyclass(frozen))]
 #[readonly::make]
 /// A finite state transducer.
 /// Constructed using [FST::from_kfst_bytes] or [FST::from_att_rows] from an in-memory representation or [FST::from_att_file] and [FST::from_kfst_file] from the file system.
@@ -1403,15 +1827,64 @@ impl FSTState {
 /// Analysis 1: [Lt][Xp]lentää[X]len[Ln][Xj]to[X]to[Sn][Ny][Bh][Bc][Ln][Xp]kone[X]konee[Sine][Ny]ssa (0)
 /// ```
 pub struct FST {
+    // final_states/rules/symbols/debug are declared twice, gated on the "python" feature rather
+    // than via #[cfg_attr(feature = "python", pyo3(get))], because pyo3's #[pyclass] macro only
+    // recognizes a literal #[pyo3(get)] attribute on a field - one arriving via cfg_attr expansion
+    // is invisible to it and is instead left dangling for rustc to reject as an unknown attribute.
+    /// A mapping from the index of a final state to its weight.
+    #[cfg(feature = "python")]
+    #[pyo3(get)]
+    pub final_states: IndexMap<u64, f64>,
     /// A mapping from the index of a final state to its weight.
+    #[cfg(not(feature = "python"))]
     pub final_states: IndexMap<u64, f64>,
     /// The transition rules of this FST: (state number -> (top symbol -> list of target state indices, bottom symbols and weights))
+    #[cfg(feature = "python")]
+    #[pyo3(get)]
+    pub rules: IndexMap<u64, IndexMap<Symbol, Vec<(u64, Symbol, f64)>>>,
+    /// The transition rules of this FST: (state number -> (top symbol -> list of target state indices, bottom symbols and weights))
+    #[cfg(not(feature = "python"))]
     pub rules: IndexMap<u64, IndexMap<Symbol, Vec<(u64, Symbol, f64)>>>,
     /// List of all the symbols in the transducer (useful for tokenization). Sorted in reverse order by length.
+    #[cfg(feature = "python")]
+    #[pyo3(get)]
+    pub symbols: Vec<Symbol>,
+    /// List of all the symbols in the transducer (useful for tokenization). Sorted in reverse order by length.
+    #[cfg(not(feature = "python"))]
     pub symbols: Vec<Symbol>,
     /// Whether this FST is in debug mode; kept for compatibility with the python implementation of KFST. It's effects on FST behaviour are undefined.
+    #[cfg(feature = "python")]
+    #[deprecated]
+    #[pyo3(get)]
+    pub debug: bool,
+    /// Whether this FST is in debug mode; kept for compatibility with the python implementation of KFST. It's effects on FST behaviour are undefined.
+    #[cfg(not(feature = "python"))]
     #[deprecated]
     pub debug: bool,
+    /// Aho-Corasick automaton over [FST::symbols], built lazily on first use and cached for the
+    /// lifetime of the FST to make [FST::split_to_symbols]/[FST::split_to_symbols_all] linear in
+    /// the length of the tokenized text instead of re-scanning the whole alphabet at every
+    /// position. Not part of the FST's logical state (two FSTs with the same rules are
+    /// equivalent regardless of whether this has been built yet), so it's not exposed to Python
+    /// and not a constructor parameter of [FST::from_rules].
+    automaton: OnceLock<SymbolAutomaton>,
+}
+
+/// Parsed KFST header/metadata/symbol-table section, shared by [FST::_from_kfst_bytes] and
+/// [FST::from_kfst_bytes_borrowed] - the only two things that need to make sense of a whole KFST
+/// byte buffer at once ([FST::_from_reader] parses the same shape, but out of a stream it can't
+/// slice `rest` out of).
+struct KfstHeader<'a> {
+    version: u16,
+    num_transitions: usize,
+    num_final_states: usize,
+    is_weighted: bool,
+    codec: Compression,
+    symbol_list: Vec<Symbol>,
+    symbol_objs: IndexSet<Symbol>,
+    /// Everything after the symbol table: the transition/final-state payload, still compressed
+    /// according to `codec` if that is [Compression::Xz].
+    rest: &'a [u8],
 }
 
 impl FST {
@@ -1510,9 +1983,14 @@ impl FST {
                 (Some(new_output_flags), Some(new_input_flags)) => {
                     let mut new_output_symbols: Vec<Symbol> = state.output_symbols.clone();
                     match (isymbol, osymbol) {
-                        (Some(isymbol), Symbol::Special(SpecialSymbol::IDENTITY)) => {
-                            new_output_symbols.push(isymbol.clone())
-                        }
+                        // IDENTITY and UNKNOWN are matched identically on the input side (both fire
+                        // only when the actual symbol is_unknown(), see [FST::_run_fst]), so they echo
+                        // the matched input symbol identically here too instead of being written out as
+                        // the literal `@_..._SYMBOL_@` placeholder - see [FST::invert].
+                        (
+                            Some(isymbol),
+                            Symbol::Special(SpecialSymbol::IDENTITY | SpecialSymbol::UNKNOWN),
+                        ) => new_output_symbols.push(isymbol.clone()),
                         _ => {
                             if !osymbol.is_epsilon() {
                                 new_output_symbols.push(osymbol.clone())
@@ -1581,11 +2059,84 @@ impl FST {
         )
     }
 
-    fn _from_kfst_bytes(kfst_bytes: &[u8]) -> Result<FST, String> {
+    /// Packs `weighted` and `codec` into the single byte the KFST header spends on the
+    /// is-weighted flag: only bit 0 was ever meaningful before [Compression] existed (every file
+    /// ever written had the rest of the byte zeroed, since `weighted.into()` only ever produced
+    /// `0` or `1`), so bit 1 doubles as the codec flag without changing the header's size or
+    /// field order.
+    fn _encode_weighted_and_codec(weighted: bool, codec: Compression) -> u8 {
+        let codec_bit: u8 = match codec {
+            Compression::Xz => 0,
+            Compression::None => 1,
+        };
+        (weighted as u8) | (codec_bit << 1)
+    }
+
+    /// Inverse of [FST::_encode_weighted_and_codec]. Bit 1 clear decodes to [Compression::Xz],
+    /// which is also what every file written before [Compression] existed decodes to, since xz
+    /// was the only codec available back then.
+    fn _decode_weighted_and_codec(byte: u8) -> (bool, Compression) {
+        let weighted = byte & 1 != 0;
+        let codec = if byte & 0b10 != 0 {
+            Compression::None
+        } else {
+            Compression::Xz
+        };
+        (weighted, codec)
+    }
+
+    /// Decompresses `input` according to `codec`, used by both [FST::_from_kfst_bytes] and
+    /// [FST::_from_reader]. The declared codec is cross-checked against the xz magic number
+    /// before being trusted as compressed: this is what makes codec detection transparent for
+    /// files with no real codec byte (everything written before [Compression] existed decodes
+    /// bit 1 as clear, i.e. [Compression::Xz], by construction) and falls back to raw bytes for
+    /// anything that doesn't actually look xz-compressed.
+    fn _decompress_payload(codec: Compression, mut input: impl BufRead) -> Result<Vec<u8>, String> {
+        let looks_like_xz = input
+            .fill_buf()
+            .map(|buf| buf.starts_with(&XZ_MAGIC))
+            .unwrap_or(false);
+        let mut decomp: Vec<u8> = Vec::new();
+        if codec == Compression::Xz && looks_like_xz {
+            lzma_rs::xz_decompress(&mut input, &mut decomp)
+                .map_err(|x| format!("failed while lzma-decompressing remainder of file: {}", x))?;
+        } else {
+            input
+                .read_to_end(&mut decomp)
+                .map_err(|x| format!("failed to read uncompressed remainder of file: {}", x))?;
+        }
+        Ok(decomp)
+    }
+
+    /// Validates the `KFST` tag and version, then parses metadata and the null-terminated symbol
+    /// table out of `kfst_bytes`. See [KfstHeader].
+    fn _parse_kfst_header(kfst_bytes: &[u8]) -> Result<KfstHeader<'_>, FstParseError> {
         // Ownership makes error handling such a pain that it makes more sense to just return an option
         // We need to parse part of the data from an owned buffer and it just makes this too comples
 
-        // Check that this is v0 kfst format
+        // Lossy text view of the whole blob, used only to compute line/column numbers and to
+        // render short token previews for FstParseError; the KFST format is otherwise binary.
+        let source_lossy = String::from_utf8_lossy(kfst_bytes).into_owned();
+        // `consumed_before` is always a tail of `kfst_bytes` here, so the length difference is
+        // its byte offset; `saturating_sub` only guards the subtraction.
+        let header_err = |consumed_before: &[u8], token: &str| {
+            FstParseError::at(
+                &source_lossy,
+                kfst_bytes.len().saturating_sub(consumed_before.len()),
+                token,
+                FstParseErrorKind::TruncatedKfstHeader,
+            )
+        };
+        let mismatch_err = |consumed_before: &[u8], token: String| {
+            FstParseError::at(
+                &source_lossy,
+                kfst_bytes.len().saturating_sub(consumed_before.len()),
+                token,
+                FstParseErrorKind::SymbolTableMismatch,
+            )
+        };
+
+        // Check that this is a supported (v0 or v1) kfst format
 
         let mut header = nom::sequence::preceded(
             nom::bytes::complete::tag("KFST"),
@@ -1593,61 +2144,69 @@ impl FST {
         );
         let (rest, version) = header
             .parse(kfst_bytes)
-            .map_err(|_| "Failed to parse header")?;
-        assert!(version == 0);
-
-        // Read metadata
+            .map_err(|_| header_err(kfst_bytes, "KFST"))?;
+        if version > 1 {
+            return Err(header_err(kfst_bytes, &format!("version {}", version)));
+        }
 
-        let mut metadata = (
-            nom::number::complete::be_u16::<&[u8], ()>,
-            nom::number::complete::be_u32,
-            nom::number::complete::be_u32,
-            nom::number::complete::u8,
-        );
-        let (rest, (num_symbols, num_transitions, num_final_states, is_weighted)) = metadata
+        // Read metadata. v0 stores `num_symbols` as a u16, capping the symbol alphabet at 65535
+        // entries; v1 widens it (and the per-transition symbol indices below) to u32.
+        let (rest, num_symbols): (&[u8], u32) = if version == 0 {
+            let (rest, num_symbols) = nom::number::complete::be_u16::<&[u8], ()>
+                .parse(rest)
+                .map_err(|_| header_err(rest, "metadata"))?;
+            (rest, num_symbols.into())
+        } else {
+            nom::number::complete::be_u32::<&[u8], ()>
+                .parse(rest)
+                .map_err(|_| header_err(rest, "metadata"))?
+        };
+        let (rest, num_transitions) = nom::number::complete::be_u32::<&[u8], ()>
+            .parse(rest)
+            .map_err(|_| header_err(rest, "metadata"))?;
+        let (rest, num_final_states) = nom::number::complete::be_u32::<&[u8], ()>
             .parse(rest)
-            .map_err(|_| "Failed to parse metadata")?;
+            .map_err(|_| header_err(rest, "metadata"))?;
+        let (rest, weighted_and_codec) = nom::number::complete::u8::<&[u8], ()>
+            .parse(rest)
+            .map_err(|_| header_err(rest, "metadata"))?;
         let num_transitions: usize = num_transitions
             .try_into()
-            .map_err(|_| "usize too small to represent transitions")?;
+            .map_err(|_| header_err(rest, "transition count"))?;
         let num_final_states: usize = num_final_states
             .try_into()
-            .map_err(|_| "usize too small to represent final states")?;
+            .map_err(|_| header_err(rest, "final state count"))?;
         // Safest conversion I can think of; theoretically it should only be 1 or 0 but Python just defers to C and C doesn't have its act together on this.
-        let is_weighted: bool = is_weighted != 0u8;
+        let (is_weighted, codec) = FST::_decode_weighted_and_codec(weighted_and_codec);
 
         // Parse out symbols
 
         let mut symbol = nom::multi::count(
             nom::sequence::terminated(nom::bytes::complete::take_until1("\0"), tag("\0")),
-            num_symbols.into(),
+            num_symbols
+                .try_into()
+                .map_err(|_| header_err(rest, "symbol count"))?,
         );
         let (rest, symbols) = symbol
             .parse(rest)
-            .map_err(|_: nom::Err<()>| "Failed to parse symbol list")?;
+            .map_err(|_: nom::Err<()>| header_err(rest, "symbol list"))?;
         let symbol_strings: Vec<&str> = symbols
             .into_iter()
-            .map(|x| std::str::from_utf8(x))
+            .map(std::str::from_utf8)
             .collect::<Result<Vec<&str>, _>>()
-            .map_err(|x| format!("Some symbol was not valid utf-8: {}", x))?;
+            .map_err(|x| mismatch_err(rest, format!("invalid utf-8 symbol: {}", x)))?;
         let symbol_list: Vec<Symbol> = symbol_strings
             .iter()
             .map(|x| {
                 Symbol::parse(x)
-                    .map_err(|x| {
-                        format!(
-                            "Some symbol while valid utf8 was not a valid symbol specifier: {}",
-                            x
-                        )
-                    })
+                    .map_err(|_| mismatch_err(rest, format!("not a valid symbol specifier: {}", x)))
                     .and_then(|(extra, sym)| {
                         if extra.is_empty() {
                             Ok(sym)
                         } else {
-                            Err(format!(
-                                "Extra data after end of symbol {}: {:?}",
-                                sym.get_symbol(),
-                                extra
+                            Err(mismatch_err(
+                                rest,
+                                format!("extra data after end of symbol {}: {:?}", sym.get_symbol(), extra),
                             ))
                         }
                     })
@@ -1655,34 +2214,243 @@ impl FST {
             .collect::<Result<Vec<Symbol>, _>>()?;
         let symbol_objs: IndexSet<Symbol> = symbol_list.iter().cloned().collect();
 
-        // From here on, data is lzma-compressed
+        Ok(KfstHeader {
+            version,
+            num_transitions,
+            num_final_states,
+            is_weighted,
+            codec,
+            symbol_list,
+            symbol_objs,
+            rest,
+        })
+    }
 
-        let mut decomp: Vec<u8> = Vec::new();
-        let mut rest_ = rest;
-        lzma_rs::xz_decompress(&mut rest_, &mut decomp)
-            .map_err(|_| "Failed to lzma-decompress remainder of file")?;
+    fn _from_kfst_bytes(kfst_bytes: &[u8]) -> Result<FST, FstParseError> {
+        let mismatch_err = |consumed_before: &[u8], token: String| {
+            FstParseError::at(
+                &String::from_utf8_lossy(kfst_bytes),
+                kfst_bytes.len().saturating_sub(consumed_before.len()),
+                token,
+                FstParseErrorKind::SymbolTableMismatch,
+            )
+        };
+
+        let header = FST::_parse_kfst_header(kfst_bytes)?;
+
+        // From here on, data is compressed according to `header.codec` (or raw, see
+        // [FST::_decompress_payload])
+
+        let decomp = FST::_decompress_payload(header.codec, header.rest)
+            .map_err(|msg| mismatch_err(header.rest, msg))?;
+
+        FST::_finish_from_kfst_parts(
+            header.version,
+            header.num_transitions,
+            header.num_final_states,
+            header.is_weighted,
+            &header.symbol_list,
+            header.symbol_objs,
+            &decomp,
+            |msg| mismatch_err(header.rest, msg),
+        )
+    }
+
+    /// Constructs a [BorrowedFST] that keeps the bulk of the transition table as offsets into
+    /// `kfst_bytes` instead of decoding it into the owned `rules` map that [FST::from_kfst_bytes]
+    /// builds. Pair this with [mmap_kfst_file] (behind the `mmap` feature) to
+    /// `lookup` a multi-megabyte transducer like `voikko.kfst` without ever materializing its
+    /// full rule table on the heap.
+    ///
+    /// Only an uncompressed ([Compression::None]) payload can actually be borrowed zero-copy; an
+    /// xz-compressed one still has to be inflated into an owned buffer before it can be parsed at
+    /// all (same as [FST::from_kfst_bytes]), so for those files this only saves the owned `rules`
+    /// map, not the decompression copy.
+    pub fn from_kfst_bytes_borrowed(kfst_bytes: &[u8]) -> Result<BorrowedFST<'_>, FstParseError> {
+        let header = FST::_parse_kfst_header(kfst_bytes)?;
+        let mismatch = |msg: String| {
+            FstParseError::at(
+                &String::from_utf8_lossy(kfst_bytes),
+                kfst_bytes.len().saturating_sub(header.rest.len()),
+                msg,
+                FstParseErrorKind::SymbolTableMismatch,
+            )
+        };
+
+        // Only borrow `header.rest` directly when it is genuinely uncompressed: an xz-compressed
+        // payload has to be inflated into a fresh owned buffer before any of it is parseable.
+        let looks_like_xz = header.rest.starts_with(&XZ_MAGIC);
+        let payload: Cow<[u8]> = if header.codec == Compression::None && !looks_like_xz {
+            Cow::Borrowed(header.rest)
+        } else {
+            Cow::Owned(FST::_decompress_payload(header.codec, header.rest).map_err(mismatch)?)
+        };
+
+        // Every transition entry has the same on-disk size, so the table can be indexed by byte
+        // offset without decoding each entry in full (see [BorrowedFST::_decode_transition_at]).
+        let symbol_idx_width: usize = if header.version == 0 { 2 } else { 4 };
+        let entry_size = 4 + 4 + symbol_idx_width * 2 + if header.is_weighted { 8 } else { 0 };
+        let transitions_len = header.num_transitions * entry_size;
+        if payload.len() < transitions_len {
+            return Err(mismatch("broken transition table".to_string()));
+        }
+
+        let mut rule_index: IndexMap<u64, IndexMap<Symbol, Vec<usize>>> = IndexMap::new();
+        for i in 0..header.num_transitions {
+            let offset = i * entry_size;
+            let entry = &payload[offset..offset + entry_size];
+            let (entry, from_state) = nom::number::complete::be_u32::<&[u8], ()>
+                .parse(entry)
+                .map_err(|_| mismatch("broken transition table".to_string()))?;
+            // `to_state` isn't needed to build `rule_index` (it's decoded later, from the stored
+            // offset, by `_decode_transition_at`), but it still has to be consumed here to keep
+            // the rest of this entry's fields aligned with `entry_size`'s layout.
+            let (entry, _to_state) = nom::number::complete::be_u32::<&[u8], ()>
+                .parse(entry)
+                .map_err(|_| mismatch("broken transition table".to_string()))?;
+            let (entry, top_symbol_idx): (&[u8], usize) = if header.version == 0 {
+                let (entry, idx) = nom::number::complete::be_u16::<&[u8], ()>
+                    .parse(entry)
+                    .map_err(|_| mismatch("broken transition table".to_string()))?;
+                (entry, idx.into())
+            } else {
+                let (entry, idx) = nom::number::complete::be_u32::<&[u8], ()>
+                    .parse(entry)
+                    .map_err(|_| mismatch("broken transition table".to_string()))?;
+                (entry, idx as usize)
+            };
+            let top_symbol = header
+                .symbol_list
+                .get(top_symbol_idx)
+                .ok_or_else(|| {
+                    mismatch(format!("transition references out-of-range symbol index {}", top_symbol_idx))
+                })?
+                .clone();
+            // `_decode_transition_at` trusts every offset in `rule_index` to point at a
+            // transition whose symbol indices are both in range, so `bottom_symbol_idx` needs
+            // the same validation as `top_symbol_idx` even though it isn't used until later.
+            let bottom_symbol_idx: usize = if header.version == 0 {
+                let (_, idx) = nom::number::complete::be_u16::<&[u8], ()>
+                    .parse(entry)
+                    .map_err(|_| mismatch("broken transition table".to_string()))?;
+                idx.into()
+            } else {
+                let (_, idx) = nom::number::complete::be_u32::<&[u8], ()>
+                    .parse(entry)
+                    .map_err(|_| mismatch("broken transition table".to_string()))?;
+                idx as usize
+            };
+            header.symbol_list.get(bottom_symbol_idx).ok_or_else(|| {
+                mismatch(format!("transition references out-of-range symbol index {}", bottom_symbol_idx))
+            })?;
+            rule_index
+                .entry(from_state.into())
+                .or_default()
+                .entry(top_symbol)
+                .or_default()
+                .push(offset);
+        }
+
+        let weight_parser = if header.is_weighted {
+            nom::number::complete::be_f64::<&[u8], ()>
+        } else {
+            |input| Ok((input, 0.0)) // Conjure up a default weight out of thin air
+        };
+        let mut final_rest = &payload[transitions_len..];
+        let mut final_states: IndexMap<u64, f64> = IndexMap::new();
+        for _ in 0..header.num_final_states {
+            let (rest, state_num) = nom::number::complete::be_u32::<&[u8], ()>
+                .parse(final_rest)
+                .map_err(|_| mismatch("broken final states".to_string()))?;
+            let (rest, weight) = weight_parser(rest).map_err(|_| mismatch("broken final states".to_string()))?;
+            final_states.insert(state_num.into(), weight);
+            final_rest = rest;
+        }
+        if !final_rest.is_empty() {
+            return Err(mismatch(format!(
+                "payload is {} bytes long when decoded but given the header, there seems to be {} bytes extra.",
+                payload.len(),
+                final_rest.len()
+            )));
+        }
 
-        // The decompressed data is - unavoidably - owned by the function
+        Ok(BorrowedFST {
+            final_states,
+            symbols: header.symbol_list,
+            version: header.version,
+            is_weighted: header.is_weighted,
+            rule_index,
+            transitions: payload,
+        })
+    }
+
+    /// Reads a format-v0 (`u16`) per-transition symbol index, widening the result to `usize`
+    /// right away. A plain `fn` rather than a closure so it can be used as a `fn` pointer in
+    /// [FST::_finish_from_kfst_parts] - a closure with this same signature hits a lifetime error
+    /// the borrow checker can't unify (an explicit `&[u8]`-containing return type combined with
+    /// an elided input lifetime).
+    fn _parse_symbol_idx_v0(input: &[u8]) -> nom::IResult<&[u8], usize, ()> {
+        let (rest, idx) = nom::number::complete::be_u16::<&[u8], ()>.parse(input)?;
+        Ok((rest, idx.into()))
+    }
+
+    /// Format-v1 (`u32`) counterpart of [FST::_parse_symbol_idx_v0].
+    fn _parse_symbol_idx_v1(input: &[u8]) -> nom::IResult<&[u8], usize, ()> {
+        let (rest, idx) = nom::number::complete::be_u32::<&[u8], ()>.parse(input)?;
+        Ok((rest, idx as usize))
+    }
+
+    /// Shared tail of [FST::_from_kfst_bytes] and [FST::_from_reader]: turns the already
+    /// lzma-decompressed transition/final-state payload into an [FST], given the symbol table
+    /// both callers have already parsed out of the header. `version` selects whether
+    /// per-transition symbol indices are read as `u16` (format v0) or `u32` (format v1, see
+    /// [FST::_to_kfst_parts]). `mismatch` builds an [FstParseError] for a given message;
+    /// [FST::_from_kfst_bytes] anchors it to a byte offset in the original buffer, while
+    /// [FST::_from_reader] has no such buffer to anchor to.
+    fn _finish_from_kfst_parts(
+        version: u16,
+        num_transitions: usize,
+        num_final_states: usize,
+        is_weighted: bool,
+        symbol_list: &[Symbol],
+        symbol_objs: IndexSet<Symbol>,
+        decomp: &[u8],
+        mismatch: impl Fn(String) -> FstParseError,
+    ) -> Result<FST, FstParseError> {
+        // The decompressed data is - unavoidably - owned by the caller
         // We promise an error type of &[u8], which we can't provide from here because of lifetimes
 
-        let transition_syntax = (
-            nom::number::complete::be_u32::<&[u8], ()>,
-            nom::number::complete::be_u32,
-            nom::number::complete::be_u16,
-            nom::number::complete::be_u16,
-        );
         let weight_parser = if is_weighted {
             nom::number::complete::be_f64
         } else {
             |input| Ok((input, 0.0)) // Conjure up a default weight out of thin air
         };
+        // v0 stores per-transition symbol indices as u16; v1 widens them to u32 to support
+        // larger symbol tables. `symbol_idx` reads whichever width `version` calls for and
+        // widens the result to usize right away, so the rest of this function doesn't need to
+        // know which version produced it.
+        let symbol_idx: fn(&[u8]) -> nom::IResult<&[u8], usize, ()> = if version == 0 {
+            FST::_parse_symbol_idx_v0
+        } else {
+            FST::_parse_symbol_idx_v1
+        };
+        let transition_syntax = (
+            nom::number::complete::be_u32::<&[u8], ()>,
+            nom::number::complete::be_u32,
+            symbol_idx,
+            symbol_idx,
+        );
         let (rest, file_rules) = many_m_n(
             num_transitions,
             num_transitions,
             (transition_syntax, weight_parser),
         )
-        .parse(decomp.as_slice())
-        .map_err(|_| "Broken transition table")?;
+        .parse(decomp)
+        .map_err(|_| mismatch("broken transition table".to_string()))?;
+        let file_rules: Vec<((u64, u64, usize, usize), f64)> = file_rules
+            .into_iter()
+            .map(|((a, b, c, d), w)| ((a.into(), b.into(), c, d), w))
+            .collect();
 
         let (rest, final_states) = many_m_n(
             num_final_states,
@@ -1690,10 +2458,14 @@ impl FST {
             (nom::number::complete::be_u32, weight_parser),
         )
         .parse(rest)
-        .map_err(|_| "Broken final states")?;
+        .map_err(|_| mismatch("broken final states".to_string()))?;
 
         if !rest.is_empty() {
-            Err(format!("lzma-compressed payload is {} bytes long when decompressed but given the header, there seems to be {} bytes extra.", decomp.len(), rest.len()))?;
+            return Err(mismatch(format!(
+                "lzma-compressed payload is {} bytes long when decompressed but given the header, there seems to be {} bytes extra.",
+                decomp.len(),
+                rest.len()
+            )));
         }
 
         // We have a vec, we want a hash map and our numbers to be i64 instead of u32
@@ -1713,12 +2485,20 @@ impl FST {
         for ((from_state, to_state, top_symbol_idx, bottom_symbol_idx), weight) in
             file_rules.into_iter()
         {
-            let from_state = from_state.into();
-            let to_state = to_state.into();
-            let top_symbol_idx: usize = top_symbol_idx.into();
-            let bottom_symbol_idx: usize = bottom_symbol_idx.into();
-            let top_symbol = symbol_list[top_symbol_idx].clone();
-            let bottom_symbol = symbol_list[bottom_symbol_idx].clone();
+            // `top_symbol_idx`/`bottom_symbol_idx` come straight from the (possibly crafted)
+            // transition table, so they aren't guaranteed to be in bounds for `symbol_list`.
+            let top_symbol = symbol_list
+                .get(top_symbol_idx)
+                .ok_or_else(|| {
+                    mismatch(format!("transition references out-of-range symbol index {}", top_symbol_idx))
+                })?
+                .clone();
+            let bottom_symbol = symbol_list
+                .get(bottom_symbol_idx)
+                .ok_or_else(|| {
+                    mismatch(format!("transition references out-of-range symbol index {}", bottom_symbol_idx))
+                })?
+                .clone();
             rules.entry(from_state).or_default();
             let handle = rules.get_mut(&from_state).unwrap();
             if !handle.contains_key(&top_symbol) {
@@ -1733,7 +2513,124 @@ impl FST {
         Ok(FST::from_rules(final_states, rules, symbols, None))
     }
 
-    fn _to_kfst_bytes(&self) -> Result<Vec<u8>, String> {
+    /// Streaming counterpart of [FST::_from_kfst_bytes]: reads the header, metadata and
+    /// null-terminated symbol table from `reader` a few bytes at a time instead of requiring the
+    /// whole file to be buffered up front, then hands `reader` straight to
+    /// [lzma_rs::xz_decompress] so the compressed payload is streamed rather than copied into an
+    /// intermediate buffer first. The tradeoff is that, unlike [FST::_from_kfst_bytes], there's no
+    /// complete buffer left to compute a byte offset/line/column against, so errors only carry a
+    /// plain message with offset 0.
+    fn _from_reader(mut reader: impl BufRead) -> Result<FST, FstParseError> {
+        let truncated =
+            |token: String| FstParseError::at("", 0, token, FstParseErrorKind::TruncatedKfstHeader);
+        let mismatch =
+            |token: String| FstParseError::at("", 0, token, FstParseErrorKind::SymbolTableMismatch);
+
+        let mut tag_buf = [0u8; 4];
+        reader
+            .read_exact(&mut tag_buf)
+            .map_err(|_| truncated("KFST".to_string()))?;
+        if &tag_buf != b"KFST" {
+            return Err(truncated("KFST".to_string()));
+        }
+
+        let mut u16_buf = [0u8; 2];
+        let mut u32_buf = [0u8; 4];
+        let mut u8_buf = [0u8; 1];
+
+        reader
+            .read_exact(&mut u16_buf)
+            .map_err(|_| truncated("version".to_string()))?;
+        let version = u16::from_be_bytes(u16_buf);
+        if version > 1 {
+            return Err(truncated(format!("version {}", version)));
+        }
+
+        // v0 stores `num_symbols` as a u16; v1 widens it to a u32 (see [FST::_to_kfst_parts]).
+        let num_symbols: u32 = if version == 0 {
+            reader
+                .read_exact(&mut u16_buf)
+                .map_err(|_| truncated("metadata".to_string()))?;
+            u16::from_be_bytes(u16_buf).into()
+        } else {
+            reader
+                .read_exact(&mut u32_buf)
+                .map_err(|_| truncated("metadata".to_string()))?;
+            u32::from_be_bytes(u32_buf)
+        };
+        reader
+            .read_exact(&mut u32_buf)
+            .map_err(|_| truncated("metadata".to_string()))?;
+        let num_transitions: usize = u32::from_be_bytes(u32_buf) as usize;
+        reader
+            .read_exact(&mut u32_buf)
+            .map_err(|_| truncated("metadata".to_string()))?;
+        let num_final_states: usize = u32::from_be_bytes(u32_buf) as usize;
+        reader
+            .read_exact(&mut u8_buf)
+            .map_err(|_| truncated("metadata".to_string()))?;
+        let (is_weighted, codec) = FST::_decode_weighted_and_codec(u8_buf[0]);
+
+        // Parse out symbols, one null-terminated string at a time
+
+        let mut symbol_list: Vec<Symbol> = Vec::with_capacity(num_symbols as usize);
+        for _ in 0..num_symbols {
+            let mut raw = Vec::new();
+            reader
+                .read_until(0, &mut raw)
+                .map_err(|_| truncated("symbol list".to_string()))?;
+            if raw.pop() != Some(0) {
+                return Err(truncated("symbol list".to_string()));
+            }
+            let text = std::str::from_utf8(&raw)
+                .map_err(|x| mismatch(format!("invalid utf-8 symbol: {}", x)))?;
+            let (extra, symbol) = Symbol::parse(text)
+                .map_err(|_| mismatch(format!("not a valid symbol specifier: {}", text)))?;
+            if !extra.is_empty() {
+                return Err(mismatch(format!(
+                    "extra data after end of symbol {}: {:?}",
+                    symbol.get_symbol(),
+                    extra
+                )));
+            }
+            symbol_list.push(symbol);
+        }
+        let symbol_objs: IndexSet<Symbol> = symbol_list.iter().cloned().collect();
+
+        // From here on, data is compressed according to `codec` (or raw, see
+        // [FST::_decompress_payload]); stream it straight out of `reader` instead of copying the
+        // rest of the input into a buffer first.
+
+        let decomp = FST::_decompress_payload(codec, reader).map_err(&mismatch)?;
+
+        FST::_finish_from_kfst_parts(
+            version,
+            num_transitions,
+            num_final_states,
+            is_weighted,
+            &symbol_list,
+            symbol_objs,
+            &decomp,
+            mismatch,
+        )
+    }
+
+    /// Figures out whether this FST is weighted, which format version it needs, and builds the
+    /// (pre-compression) transition-table and final-state payload shared by
+    /// [FST::_to_kfst_bytes] and [FST::_to_writer]. Returns
+    /// `(weighted, version, transition_count, to_compress)`.
+    ///
+    /// `version` is `0` unless the symbol table is too large for a v0 file to index (more than
+    /// `u16::MAX` symbols), in which case it's `1`: v1 keeps the same header shape but widens
+    /// `num_symbols` and the per-transition top/bottom symbol indices to `u32` (see
+    /// [FST::_finish_from_kfst_parts]).
+    ///
+    /// When `canonical` is set, transitions and final states are written in the order given by
+    /// [FST::_canonical_transitions]/[FST::_canonical_final_states] instead of the iteration
+    /// order of [FST::rules]/[FST::final_states], and a weight of `-0.0` is normalized to `0.0`,
+    /// so that two FSTs which are equal as graphs produce byte-identical output regardless of
+    /// insertion order. See [FST::to_kfst_bytes_canonical].
+    fn _to_kfst_parts(&self, canonical: bool) -> Result<(bool, u16, u32, Vec<u8>), String> {
         // 1. Figure out if this transducer if weighted & count transitions
 
         let mut weighted = false;
@@ -1745,44 +2642,35 @@ impl FST {
             }
         }
 
-        let mut transitions: u32 = 0;
+        let mut transition_list: Vec<(u64, Symbol, u64, Symbol, f64)> = vec![];
 
-        for (_, transition_table) in self.rules.iter() {
-            for transition in transition_table.values() {
-                for (_, _, weight) in transition.iter() {
-                    if (*weight) != 0.0 {
+        for (&source_state, transition_table) in self.rules.iter() {
+            for (top_symbol, transition) in transition_table.iter() {
+                for &(target_state, ref bottom_symbol, weight) in transition.iter() {
+                    if weight != 0.0 {
                         weighted = true;
                     }
-                    transitions += 1;
+                    transition_list.push((
+                        source_state,
+                        top_symbol.clone(),
+                        target_state,
+                        bottom_symbol.clone(),
+                        weight,
+                    ));
                 }
             }
         }
 
-        // Construct header
+        if canonical {
+            FST::_sort_canonical_transitions(&mut transition_list);
+        }
 
-        let mut result: Vec<u8> = "KFST".into();
-        result.extend(0u16.to_be_bytes());
-        let symbol_len: u16 = self
-            .symbols
-            .len()
-            .try_into()
-            .map_err(|x| format!("Too many symbols to represent as u16: {}", x))?;
-        result.extend(symbol_len.to_be_bytes());
-        result.extend(transitions.to_be_bytes());
-        let num_states: u32 = self
-            .final_states
+        let transitions: u32 = transition_list
             .len()
             .try_into()
-            .map_err(|x| format!("Too many final states to represent as u32: {}", x))?;
-        result.extend(num_states.to_be_bytes());
-        result.push(weighted.into()); // Promises 0 for false and 1 for true
+            .map_err(|x| format!("Too many transitions to represent as u32: {}", x))?;
 
-        // Dump symbols
-
-        for symbol in self.symbols.iter() {
-            result.extend(symbol.get_symbol().into_bytes());
-            result.push(0); // Add null-terminators
-        }
+        let version: u16 = if self.symbols.len() > u16::MAX as usize { 1 } else { 0 };
 
         // lzma-compressed part of payload
 
@@ -1790,80 +2678,393 @@ impl FST {
 
         // Push transition table to compressible buffer
 
-        for (source_state, transition_table) in self.rules.iter() {
-            for (top_symbol, transition) in transition_table.iter() {
-                for (target_state, bottom_symbol, weight) in transition.iter() {
-                    let source_state: usize = (*source_state).try_into().map_err(|x| {
-                        format!(
-                            "Can't represent source state {} as u32: {}",
-                            source_state, x
-                        )
-                    })?;
-                    let target_state: usize = (*target_state).try_into().map_err(|x| {
-                        format!(
-                            "Can't represent target state {} as u32: {}",
-                            target_state, x
-                        )
-                    })?;
-                    let top_index: u16 = self
-                        .symbols
-                        .binary_search(top_symbol)
-                        .map_err(|_| {
-                            format!("Top symbol {:?} not found in FST symbol list", top_symbol)
-                        })
-                        .and_then(|x| {
-                            x.try_into().map_err(|x| {
-                                format!("Can't represent top symbol index as u16: {}", x)
-                            })
-                        })?;
-                    let bottom_index: u16 = self
-                        .symbols
-                        .binary_search(bottom_symbol)
-                        .map_err(|_| {
-                            format!("Top symbol {:?} not found in FST symbol list", top_symbol)
-                        })
-                        .and_then(|x| {
-                            x.try_into().map_err(|x| {
-                                format!("Can't represent bottom symbol index as u16: {}", x)
-                            })
-                        })?;
-                    to_compress.extend(source_state.to_be_bytes());
-                    to_compress.extend(target_state.to_be_bytes());
-                    to_compress.extend(top_index.to_be_bytes());
-                    to_compress.extend(bottom_index.to_be_bytes());
-                    if weighted {
-                        to_compress.extend(weight.to_be_bytes());
-                    } else {
-                        assert!(*weight == 0.0);
-                    }
-                }
+        for (source_state, top_symbol, target_state, bottom_symbol, weight) in transition_list.iter() {
+            let source_state: u32 = (*source_state).try_into().map_err(|x| {
+                format!(
+                    "Can't represent source state {} as u32: {}",
+                    source_state, x
+                )
+            })?;
+            let target_state: u32 = (*target_state).try_into().map_err(|x| {
+                format!(
+                    "Can't represent target state {} as u32: {}",
+                    target_state, x
+                )
+            })?;
+            let top_index = self.symbols.binary_search(top_symbol).map_err(|_| {
+                format!("Top symbol {:?} not found in FST symbol list", top_symbol)
+            })?;
+            let bottom_index = self.symbols.binary_search(bottom_symbol).map_err(|_| {
+                format!("Top symbol {:?} not found in FST symbol list", top_symbol)
+            })?;
+            to_compress.extend(source_state.to_be_bytes());
+            to_compress.extend(target_state.to_be_bytes());
+            if version == 0 {
+                let top_index: u16 = top_index.try_into().map_err(|x| {
+                    format!("Can't represent top symbol index as u16: {}", x)
+                })?;
+                let bottom_index: u16 = bottom_index.try_into().map_err(|x| {
+                    format!("Can't represent bottom symbol index as u16: {}", x)
+                })?;
+                to_compress.extend(top_index.to_be_bytes());
+                to_compress.extend(bottom_index.to_be_bytes());
+            } else {
+                let top_index: u32 = top_index.try_into().map_err(|x| {
+                    format!("Can't represent top symbol index as u32: {}", x)
+                })?;
+                let bottom_index: u32 = bottom_index.try_into().map_err(|x| {
+                    format!("Can't represent bottom symbol index as u32: {}", x)
+                })?;
+                to_compress.extend(top_index.to_be_bytes());
+                to_compress.extend(bottom_index.to_be_bytes());
+            }
+            if weighted {
+                let weight = if canonical && *weight == 0.0 { 0.0 } else { *weight };
+                to_compress.extend(weight.to_be_bytes());
+            } else {
+                assert!(*weight == 0.0);
             }
         }
 
         // Push final states to compressible buffer
 
-        for (&final_state, weight) in self.final_states.iter() {
+        let mut final_state_list: Vec<(u64, f64)> = self
+            .final_states
+            .iter()
+            .map(|(&final_state, &weight)| (final_state, weight))
+            .collect();
+        if canonical {
+            final_state_list.sort_by_key(|&(final_state, _)| final_state);
+        }
+
+        for (final_state, weight) in final_state_list {
             let final_state: u32 = final_state
                 .try_into()
                 .map_err(|x| format!("Can't represent final state index as u32: {}", x))?;
             to_compress.extend(final_state.to_be_bytes());
             if weighted {
+                let weight = if canonical && weight == 0.0 { 0.0 } else { weight };
                 to_compress.extend(weight.to_be_bytes());
             } else {
-                assert!(*weight == 0.0);
+                assert!(weight == 0.0);
             }
         }
 
-        // Compress compressible buffer
+        Ok((weighted, version, transitions, to_compress))
+    }
+
+    /// Sorts `transitions` by `(from_state, top_symbol, bottom_symbol, to_state, weight)`, using
+    /// [Symbol]'s total order - the canonical transition order shared by
+    /// [FST::to_att_code_canonical] and [FST::to_kfst_bytes_canonical].
+    fn _sort_canonical_transitions(transitions: &mut [(u64, Symbol, u64, Symbol, f64)]) {
+        transitions.sort_by(|a, b| {
+            a.0.cmp(&b.0)
+                .then_with(|| a.1.cmp(&b.1))
+                .then_with(|| a.3.cmp(&b.3))
+                .then_with(|| a.2.cmp(&b.2))
+                .then_with(|| a.4.partial_cmp(&b.4).unwrap_or(std::cmp::Ordering::Equal))
+        });
+    }
+
+    /// Shared body of the public `to_kfst_bytes`/`to_kfst_bytes_with` wrappers, kept in one place
+    /// since pyo3 needs its own entry point (for the `codec` default argument) distinct from the
+    /// non-python one.
+    fn _to_kfst_bytes_with(&self, codec: Compression, canonical: bool) -> KFSTResult<Vec<u8>> {
+        match self._to_kfst_bytes(codec, canonical) {
+            Ok(x) => Ok(x),
+            Err(x) => value_error(x),
+        }
+    }
+
+    fn _to_kfst_bytes(&self, codec: Compression, canonical: bool) -> Result<Vec<u8>, String> {
+        let (weighted, version, transitions, to_compress) = self._to_kfst_parts(canonical)?;
+
+        // Construct header
+
+        let mut result: Vec<u8> = "KFST".into();
+        result.extend(version.to_be_bytes());
+        if version == 0 {
+            let symbol_len: u16 = self
+                .symbols
+                .len()
+                .try_into()
+                .map_err(|x| format!("Too many symbols to represent as u16: {}", x))?;
+            result.extend(symbol_len.to_be_bytes());
+        } else {
+            let symbol_len: u32 = self
+                .symbols
+                .len()
+                .try_into()
+                .map_err(|x| format!("Too many symbols to represent as u32: {}", x))?;
+            result.extend(symbol_len.to_be_bytes());
+        }
+        result.extend(transitions.to_be_bytes());
+        let num_states: u32 = self
+            .final_states
+            .len()
+            .try_into()
+            .map_err(|x| format!("Too many final states to represent as u32: {}", x))?;
+        result.extend(num_states.to_be_bytes());
+        result.push(FST::_encode_weighted_and_codec(weighted, codec));
+
+        // Dump symbols
+
+        for symbol in self.symbols.iter() {
+            result.extend(symbol.get_symbol().into_bytes());
+            result.push(0); // Add null-terminators
+        }
+
+        // Compress (or not, per `codec`) the compressible buffer
 
-        let mut compressed = vec![];
-        lzma_compress(&mut to_compress.as_slice(), &mut compressed)
-            .map_err(|x| format!("Failed while compressing with lzma_rs: {}", x))?;
-        result.extend(compressed);
+        match codec {
+            Compression::Xz => {
+                let mut compressed = vec![];
+                lzma_rs::xz_compress(&mut to_compress.as_slice(), &mut compressed)
+                    .map_err(|x| format!("Failed while compressing with lzma_rs: {}", x))?;
+                result.extend(compressed);
+            }
+            Compression::None => result.extend(to_compress),
+        }
 
         Ok(result)
     }
 
+    /// Streaming counterpart of [FST::_to_kfst_bytes]: writes the header, symbol table and
+    /// compressed (or raw, per `codec`) payload straight to `writer` as they're produced,
+    /// instead of building the whole output file in memory first.
+    fn _to_writer(&self, mut writer: impl Write, codec: Compression) -> Result<(), String> {
+        let (weighted, version, transitions, to_compress) = self._to_kfst_parts(false)?;
+        let io_err = |x: std::io::Error| format!("Failed to write KFST data: {}", x);
+
+        writer.write_all(b"KFST").map_err(io_err)?;
+        writer.write_all(&version.to_be_bytes()).map_err(io_err)?;
+        if version == 0 {
+            let symbol_len: u16 = self
+                .symbols
+                .len()
+                .try_into()
+                .map_err(|x| format!("Too many symbols to represent as u16: {}", x))?;
+            writer.write_all(&symbol_len.to_be_bytes()).map_err(io_err)?;
+        } else {
+            let symbol_len: u32 = self
+                .symbols
+                .len()
+                .try_into()
+                .map_err(|x| format!("Too many symbols to represent as u32: {}", x))?;
+            writer.write_all(&symbol_len.to_be_bytes()).map_err(io_err)?;
+        }
+        writer.write_all(&transitions.to_be_bytes()).map_err(io_err)?;
+        let num_states: u32 = self
+            .final_states
+            .len()
+            .try_into()
+            .map_err(|x| format!("Too many final states to represent as u32: {}", x))?;
+        writer.write_all(&num_states.to_be_bytes()).map_err(io_err)?;
+        writer
+            .write_all(&[FST::_encode_weighted_and_codec(weighted, codec)])
+            .map_err(io_err)?;
+
+        for symbol in self.symbols.iter() {
+            writer.write_all(symbol.get_symbol().as_bytes()).map_err(io_err)?;
+            writer.write_all(&[0]).map_err(io_err)?; // Add null-terminators
+        }
+
+        match codec {
+            Compression::Xz => {
+                lzma_rs::xz_compress(&mut to_compress.as_slice(), &mut writer)
+                    .map_err(|x| format!("Failed while compressing with lzma_rs: {}", x))?;
+            }
+            Compression::None => writer.write_all(&to_compress).map_err(io_err)?,
+        }
+
+        Ok(())
+    }
+
+    /// Serializes the current transducer into the KFST binary format and writes it to `writer`,
+    /// without ever materializing the whole output as a single in-memory [Vec]. See
+    /// [FST::to_kfst_bytes] for more details on the format. Only available outside the `python`
+    /// feature, since pyo3 methods can't take a generic [std::io::Write] parameter; Python callers
+    /// should use [FST::to_kfst_bytes]/`to_kfst_file` instead.
+    #[cfg(not(feature = "python"))]
+    pub fn to_writer(&self, writer: impl Write) -> KFSTResult<()> {
+        match self._to_writer(writer, Compression::Xz) {
+            Ok(x) => Ok(x),
+            Err(x) => value_error(x),
+        }
+    }
+
+    /// Checks the `KPAK` magic and returns the version field (not checked here) together with
+    /// the remaining bytes: the sequence of
+    /// length-prefixed records (see [PACK_RECORD_FST]/[PACK_RECORD_METADATA]).
+    fn _parse_pack_header(pack_bytes: &[u8]) -> Result<(u16, &[u8]), FstParseError> {
+        let header_err = |token: &str| {
+            FstParseError::at(
+                &String::from_utf8_lossy(pack_bytes),
+                0,
+                token,
+                FstParseErrorKind::TruncatedKfstHeader,
+            )
+        };
+        let mut header = nom::sequence::preceded(
+            nom::bytes::complete::tag("KPAK"),
+            nom::number::complete::be_u16::<&[u8], ()>,
+        );
+        header
+            .parse(pack_bytes)
+            .map(|(rest, version)| (version, rest))
+            .map_err(|_| header_err("KPAK"))
+    }
+
+    /// Splits the record section of a `kfstpack` container (as returned by
+    /// [FST::_parse_pack_header]) into `(tag, payload)` pairs. A tag this reader doesn't
+    /// recognise is still returned - skipping over one is just a matter of not matching on it,
+    /// since every record can be skipped by its length regardless of its tag.
+    fn _pack_records(mut rest: &[u8]) -> Result<Vec<(u8, &[u8])>, FstParseError> {
+        let record_err = |token: &str| {
+            FstParseError::at("", 0, token, FstParseErrorKind::SymbolTableMismatch)
+        };
+        let mut records = Vec::new();
+        while !rest.is_empty() {
+            let (after_tag, tag) = nom::number::complete::u8::<&[u8], ()>
+                .parse(rest)
+                .map_err(|_| record_err("record tag"))?;
+            let (after_len, len) = nom::number::complete::be_u32::<&[u8], ()>
+                .parse(after_tag)
+                .map_err(|_| record_err("record length"))?;
+            let len: usize = len.try_into().map_err(|_| record_err("record length"))?;
+            if after_len.len() < len {
+                return Err(record_err("truncated record payload"));
+            }
+            let (payload, next) = after_len.split_at(len);
+            records.push((tag, payload));
+            rest = next;
+        }
+        Ok(records)
+    }
+
+    /// Decodes a [PACK_RECORD_FST] record's payload into its name and embedded KFST bytes.
+    fn _decode_pack_fst_entry(payload: &[u8]) -> Result<(String, &[u8]), FstParseError> {
+        let record_err = |token: &str| {
+            FstParseError::at("", 0, token, FstParseErrorKind::SymbolTableMismatch)
+        };
+        let (rest, name_len) = nom::number::complete::be_u32::<&[u8], ()>
+            .parse(payload)
+            .map_err(|_| record_err("fst entry name length"))?;
+        let name_len: usize = name_len.try_into().map_err(|_| record_err("fst entry name length"))?;
+        if rest.len() < name_len {
+            return Err(record_err("truncated fst entry name"));
+        }
+        let (name_bytes, kfst_bytes) = rest.split_at(name_len);
+        let name = std::str::from_utf8(name_bytes)
+            .map_err(|_| record_err("fst entry name is not valid utf-8"))?
+            .to_string();
+        Ok((name, kfst_bytes))
+    }
+
+    fn _from_pack_bytes(pack_bytes: &[u8], name: &str) -> Result<FST, FstParseError> {
+        let (_version, rest) = FST::_parse_pack_header(pack_bytes)?;
+        for (tag, payload) in FST::_pack_records(rest)? {
+            if tag == PACK_RECORD_FST {
+                let (entry_name, kfst_bytes) = FST::_decode_pack_fst_entry(payload)?;
+                if entry_name == name {
+                    return FST::_from_kfst_bytes(kfst_bytes);
+                }
+            }
+        }
+        Err(FstParseError::at(
+            "",
+            0,
+            format!("no fst named {:?} in pack", name),
+            FstParseErrorKind::SymbolTableMismatch,
+        ))
+    }
+
+    /// Construct an FST named `name` out of a `kfstpack` container built by [PackBuilder]. See
+    /// [PackBuilder] for the container's layout.
+    #[cfg(not(feature = "python"))]
+    pub fn from_pack_bytes(pack_bytes: &[u8], name: &str) -> KFSTResult<FST> {
+        match FST::_from_pack_bytes(pack_bytes, name) {
+            Ok(x) => Ok(x),
+            Err(x) => parse_error(x),
+        }
+    }
+
+    fn _pack_names(pack_bytes: &[u8]) -> Result<Vec<String>, FstParseError> {
+        let (_version, rest) = FST::_parse_pack_header(pack_bytes)?;
+        FST::_pack_records(rest)?
+            .into_iter()
+            .filter(|&(tag, _)| tag == PACK_RECORD_FST)
+            .map(|(_, payload)| FST::_decode_pack_fst_entry(payload).map(|(name, _)| name))
+            .collect()
+    }
+
+    /// Lists the names of every transducer embedded in a `kfstpack` container, in the order
+    /// [PackBuilder::add_fst] appended them.
+    #[cfg(not(feature = "python"))]
+    pub fn pack_names(pack_bytes: &[u8]) -> KFSTResult<Vec<String>> {
+        match FST::_pack_names(pack_bytes) {
+            Ok(x) => Ok(x),
+            Err(x) => parse_error(x),
+        }
+    }
+
+    fn _pack_metadata(pack_bytes: &[u8]) -> Result<IndexMap<String, String>, FstParseError> {
+        let record_err = |token: &str| {
+            FstParseError::at("", 0, token, FstParseErrorKind::SymbolTableMismatch)
+        };
+        let (_version, rest) = FST::_parse_pack_header(pack_bytes)?;
+        let mut metadata = IndexMap::new();
+        for (tag, mut payload) in FST::_pack_records(rest)? {
+            if tag != PACK_RECORD_METADATA {
+                continue;
+            }
+            let (rest, pair_count) = nom::number::complete::be_u32::<&[u8], ()>
+                .parse(payload)
+                .map_err(|_| record_err("metadata pair count"))?;
+            payload = rest;
+            for _ in 0..pair_count {
+                let (rest, key_len) = nom::number::complete::be_u32::<&[u8], ()>
+                    .parse(payload)
+                    .map_err(|_| record_err("metadata key length"))?;
+                let key_len: usize = key_len.try_into().map_err(|_| record_err("metadata key length"))?;
+                if rest.len() < key_len {
+                    return Err(record_err("truncated metadata key"));
+                }
+                let (key_bytes, rest) = rest.split_at(key_len);
+                let (rest, value_len) = nom::number::complete::be_u32::<&[u8], ()>
+                    .parse(rest)
+                    .map_err(|_| record_err("metadata value length"))?;
+                let value_len: usize = value_len.try_into().map_err(|_| record_err("metadata value length"))?;
+                if rest.len() < value_len {
+                    return Err(record_err("truncated metadata value"));
+                }
+                let (value_bytes, rest) = rest.split_at(value_len);
+                let key = std::str::from_utf8(key_bytes)
+                    .map_err(|_| record_err("metadata key is not valid utf-8"))?
+                    .to_string();
+                let value = std::str::from_utf8(value_bytes)
+                    .map_err(|_| record_err("metadata value is not valid utf-8"))?
+                    .to_string();
+                // Last entry wins: a plain insert overwrites any earlier value for the same key
+                // without needing a second pass to find and remove the stale one first.
+                metadata.insert(key, value);
+                payload = rest;
+            }
+        }
+        Ok(metadata)
+    }
+
+    /// Reads the free-form metadata record of a `kfstpack` container (language, weight
+    /// interpretation, creation tool, ...), if [PackBuilder::set_metadata] was used to set one.
+    /// Duplicate keys resolve last-entry-wins, per [PackBuilder]'s on-disk layout.
+    #[cfg(not(feature = "python"))]
+    pub fn pack_metadata(pack_bytes: &[u8]) -> KFSTResult<IndexMap<String, String>> {
+        match FST::_pack_metadata(pack_bytes) {
+            Ok(x) => Ok(x),
+            Err(x) => parse_error(x),
+        }
+    }
+
     fn _from_rules(
         final_states: IndexMap<u64, f64>,
         rules: IndexMap<u64, IndexMap<Symbol, Vec<(u64, Symbol, f64)>>>,
@@ -1879,6 +3080,7 @@ impl FST {
             rules,
             symbols: new_symbols,
             debug: debug.unwrap_or(false),
+            automaton: OnceLock::new(),
         }
     }
 
@@ -1916,9 +3118,26 @@ impl FST {
     }
 
     fn _from_att_code(att_code: String, debug: bool) -> KFSTResult<FST> {
+        // A single ATT row longer than this is almost certainly not a real transducer; reject it
+        // up front instead of letting a malformed or adversarial file force a huge `split`/`parse`.
+        const MAX_ATT_LINE_LEN: usize = 1 << 20;
+
         let mut rows: Vec<Result<(u64, f64), (u64, u64, Symbol, Symbol, f64)>> = vec![];
 
-        for (lineno, line) in att_code.lines().enumerate() {
+        // `line` is always a substring of `att_code` (see `str::lines`), so this pointer
+        // subtraction is a valid, allocation-free way to recover a line's byte offset for
+        // FstParseError without re-scanning the string.
+        let line_offset = |line: &str| line.as_ptr() as usize - att_code.as_ptr() as usize;
+
+        for line in att_code.lines() {
+            if line.len() > MAX_ATT_LINE_LEN {
+                return parse_error(FstParseError::at(
+                    &att_code,
+                    line_offset(line),
+                    line.chars().take(64).collect::<String>(),
+                    FstParseErrorKind::MalformedAttRow,
+                ));
+            }
             let elements: Vec<&str> = line.split("\t").collect();
             if elements.len() == 1 || elements.len() == 2 {
                 let state = elements[0].parse::<u64>().ok();
@@ -1932,9 +3151,11 @@ impl FST {
                         rows.push(Ok((state, weight)));
                     }
                     _ => {
-                        return value_error(format!(
-                            "Failed to parse att code on line {}:\n{}",
-                            lineno, line
+                        return parse_error(FstParseError::at(
+                            &att_code,
+                            line_offset(line),
+                            line,
+                            FstParseErrorKind::MalformedAttRow,
                         ))
                     }
                 }
@@ -1959,9 +3180,11 @@ impl FST {
                         rows.push(Err((state_1, state_2, symbol_1, symbol_2, weight)));
                     }
                     _ => {
-                        return value_error(format!(
-                            "Failed to parse att code on line {}:\n{}",
-                            lineno, line
+                        return parse_error(FstParseError::at(
+                            &att_code,
+                            line_offset(line),
+                            line,
+                            FstParseErrorKind::MalformedAttRow,
                         ));
                     }
                 }
@@ -2045,7 +3268,7 @@ impl FST {
     fn __from_kfst_bytes(kfst_bytes: &[u8], debug: bool) -> KFSTResult<FST> {
         match FST::_from_kfst_bytes(kfst_bytes) {
             Ok(x) => Ok(x),
-            Err(x) => value_error(x),
+            Err(x) => parse_error(x),
         }
     }
 
@@ -2057,29 +3280,119 @@ impl FST {
         FST::__from_kfst_bytes(kfst_bytes, debug)
     }
 
+    #[allow(unused)]
+    fn __from_reader(reader: impl BufRead, debug: bool) -> KFSTResult<FST> {
+        match FST::_from_reader(reader) {
+            Ok(x) => Ok(x),
+            Err(x) => parse_error(x),
+        }
+    }
+
+    /// Construct an FST instance by streaming the KFST binary representation out of `reader`
+    /// instead of requiring it to already be fully buffered in memory, as [FST::from_kfst_bytes]
+    /// does. This lets a large transducer be loaded directly from stdin, a socket or a
+    /// memory-mapped file without doubling peak memory. Unlike [FST::from_kfst_bytes], parse
+    /// errors from a streamed read don't carry a byte offset/line/column, since there's no
+    /// complete buffer left to compute one against. Only available outside the `python` feature,
+    /// since pyo3 methods can't take a generic [BufRead] parameter; Python callers should use
+    /// [FST::from_kfst_bytes] instead. `debug` is passed along to [FST::debug].
+    #[cfg(not(feature = "python"))]
+    pub fn from_reader(reader: impl BufRead, debug: bool) -> KFSTResult<FST> {
+        FST::__from_reader(reader, debug)
+    }
+
+    fn _sniff_format(bytes: &[u8]) -> FstFormat {
+        if bytes.starts_with(b"KFST") {
+            FstFormat::Kfst
+        } else {
+            FstFormat::Att
+        }
+    }
+
+    /// Looks at the leading bytes of `bytes` and reports which format [FST::from_bytes] would
+    /// parse them as, without actually parsing them. Currently this is just a check for the
+    /// `"KFST"` magic tag, since anything else is assumed to be AT&T text.
+    #[cfg(not(feature = "python"))]
+    pub fn sniff_format(bytes: &[u8]) -> FstFormat {
+        FST::_sniff_format(bytes)
+    }
+
+    fn _from_bytes(bytes: &[u8], debug: bool) -> KFSTResult<FST> {
+        match FST::_sniff_format(bytes) {
+            FstFormat::Kfst => FST::__from_kfst_bytes(bytes, debug),
+            FstFormat::Att => match std::str::from_utf8(bytes) {
+                Ok(att_code) => FST::_from_att_code(att_code.to_string(), debug),
+                Err(_) => parse_error(FstParseError::at(
+                    "",
+                    0,
+                    "<binary data that isn't valid UTF-8 or a KFST file>",
+                    FstParseErrorKind::MalformedAttRow,
+                )),
+            },
+        }
+    }
+
+    /// Construct an FST from a byte buffer that is either AT&T text or the binary KFST format,
+    /// auto-detecting which one by the presence of the `"KFST"` magic tag (see [FST::sniff_format]).
+    /// `debug` is passed along to [FST::debug].
+    #[cfg(not(feature = "python"))]
+    pub fn from_bytes(bytes: &[u8], debug: bool) -> KFSTResult<FST> {
+        FST::_from_bytes(bytes, debug)
+    }
+
+    fn _from_file(path: String, debug: bool) -> KFSTResult<FST> {
+        match File::open(Path::new(&path)) {
+            Ok(mut file) => {
+                let mut bytes: Vec<u8> = vec![];
+                file.read_to_end(&mut bytes).map_err(|err| {
+                    io_error::<()>(format!("Failed to read from file {}:\n{}", path, err))
+                        .unwrap_err()
+                })?;
+                FST::_from_bytes(&bytes, debug)
+            }
+            Err(err) => io_error(format!("Failed to open file {}:\n{}", path, err)),
+        }
+    }
+
+    /// Construct an FST from a file on the file system that is either AT&T text or the binary
+    /// KFST format, auto-detecting which one by the file's content (see [FST::from_bytes]).
+    /// `debug` is passed along to [FST::debug].
+    #[cfg(not(feature = "python"))]
+    pub fn from_file(path: String, debug: bool) -> KFSTResult<FST> {
+        FST::_from_file(path, debug)
+    }
+
+    /// Returns the [SymbolAutomaton] for this FST's alphabet ([FST::symbols]), building and
+    /// caching it on first use (see [FST::automaton]).
+    fn _symbol_automaton(&self) -> &SymbolAutomaton {
+        self.automaton.get_or_init(|| SymbolAutomaton::build(&self.symbols))
+    }
+
     fn _split_to_symbols(&self, text: &str, allow_unknown: bool) -> Option<Vec<Symbol>> {
+        let automaton = self._symbol_automaton();
+        let chars: Vec<char> = text.chars().collect();
+        let edges_by_start = automaton.match_edges(&chars);
+
         let mut result = vec![];
-        let mut pos = text.chars();
-        'outer: while pos.size_hint().0 > 0 {
-            for symbol in self.symbols.iter() {
-                let symbol_string = symbol.get_symbol();
-                if pos.as_str().starts_with(&symbol_string) {
+        let mut pos = 0;
+        while pos < chars.len() {
+            match edges_by_start[pos].iter().min_by_key(|(_, symbol)| automaton.rank[symbol]) {
+                Some((end, symbol)) => {
                     result.push(symbol.clone());
-                    // Consume correct amount of characters from iterator
-                    for _ in symbol_string.chars() {
-                        pos.next();
+                    pos = *end;
+                }
+                None => {
+                    if allow_unknown {
+                        result.push(Symbol::String(StringSymbol {
+                            string: intern(chars[pos].to_string()),
+                            unknown: true,
+                        }));
+                        pos += 1;
+                    } else {
+                        return None;
                     }
-                    continue 'outer;
                 }
             }
-            if allow_unknown {
-                result.push(Symbol::String(StringSymbol {
-                    string: intern(pos.next().unwrap().to_string()),
-                    unknown: true,
-                }));
-            } else {
-                return None;
-            }
         }
         Some(result)
     }
@@ -2093,6 +3406,66 @@ impl FST {
         self._split_to_symbols(text, allow_unknown)
     }
 
+    /// Shared body of [FST::split_to_symbols_all], enumerating every way to tokenize `text`
+    /// into this transducer's alphabet, instead of only the greedy first match
+    /// [FST::_split_to_symbols] picks at each position. `allow_unknown` has the same meaning as
+    /// in [FST::_split_to_symbols], except that a fallback unknown symbol is only ever considered
+    /// at a position where no real alphabet symbol matches at all - positions with at least one
+    /// real match are never also segmented as unknown, mirroring the greedy algorithm's
+    /// preference for real symbols over the unknown fallback.
+    fn _split_to_symbols_all(&self, text: &str, allow_unknown: bool) -> Option<Vec<Vec<Symbol>>> {
+        let automaton = self._symbol_automaton();
+        let chars: Vec<char> = text.chars().collect();
+        let edges_by_start = automaton.match_edges(&chars);
+        let len = chars.len();
+
+        // segmentations[pos] holds every way to segment chars[pos..] into symbols; filled in from
+        // the end backwards so that by the time a position is processed, every position it could
+        // transition to is already resolved.
+        let mut segmentations: Vec<Option<Vec<Vec<Symbol>>>> = vec![None; len + 1];
+        segmentations[len] = Some(vec![vec![]]);
+
+        for pos in (0..len).rev() {
+            let mut edges = edges_by_start[pos].clone();
+            if edges.is_empty() {
+                if !allow_unknown {
+                    return None;
+                }
+                edges.push((
+                    pos + 1,
+                    Symbol::String(StringSymbol {
+                        string: intern(chars[pos].to_string()),
+                        unknown: true,
+                    }),
+                ));
+            }
+
+            let mut continuations = vec![];
+            for (end, symbol) in edges {
+                let Some(tails) = &segmentations[end] else { continue };
+                for tail in tails {
+                    let mut segmentation = vec![symbol.clone()];
+                    segmentation.extend(tail.iter().cloned());
+                    continuations.push(segmentation);
+                }
+            }
+            if continuations.is_empty() {
+                return None;
+            }
+            segmentations[pos] = Some(continuations);
+        }
+
+        segmentations[0].take()
+    }
+
+    /// Like [FST::split_to_symbols], but returns every way `text` can be tokenized into this
+    /// transducer's alphabet instead of only the first (greedy) one, for alphabets where more
+    /// than one symbol can match at the same position.
+    #[cfg(not(feature = "python"))]
+    pub fn split_to_symbols_all(&self, text: &str, allow_unknown: bool) -> Option<Vec<Vec<Symbol>>> {
+        self._split_to_symbols_all(text, allow_unknown)
+    }
+
     fn __run_fst(
         &self,
         input_symbols: Vec<Symbol>,
@@ -2182,96 +3555,1243 @@ impl FST {
     ) -> KFSTResult<Vec<(String, f64)>> {
         self._lookup(input, state, allow_unknown)
     }
+
+    fn _lookup_all(
+        &self,
+        input: &str,
+        state: FSTState,
+        allow_unknown: bool,
+    ) -> KFSTResult<Vec<(String, f64)>> {
+        let segmentations = self.split_to_symbols_all(input, allow_unknown);
+        match segmentations {
+            None => {
+                tokenization_exception(format!("Input cannot be split into symbols: {}", input))
+            }
+            Some(segmentations) => {
+                let mut dedup: IndexSet<String> = IndexSet::new();
+                let mut result: Vec<(String, f64)> = vec![];
+                let mut finished_paths: Vec<_> = segmentations
+                    .into_iter()
+                    .flat_map(|input_symbols| self.run_fst(input_symbols, state.clone(), false))
+                    .filter(|(finished, _, _)| *finished)
+                    .collect();
+                finished_paths
+                    .sort_by(|a, b| a.2.path_weight.partial_cmp(&b.2.path_weight).unwrap());
+                for finished in finished_paths {
+                    let output_string: String = finished
+                        .2
+                        .output_symbols
+                        .iter()
+                        .map(|x| x.get_symbol())
+                        .collect::<Vec<String>>()
+                        .join("");
+                    if dedup.contains(&output_string) {
+                        continue;
+                    }
+                    dedup.insert(output_string.clone());
+                    result.push((output_string, finished.2.path_weight));
+                }
+                Ok(result)
+            }
+        }
+    }
+
+    #[cfg(not(feature = "python"))]
+    /// Like [FST::lookup], but tokenizes `input` with [FST::split_to_symbols_all] instead of
+    /// [FST::split_to_symbols] and transduces every resulting segmentation, merging and
+    /// deduplicating their outputs the same way [FST::lookup] deduplicates the paths of a single
+    /// segmentation. Useful for alphabets where [FST::lookup]'s greedy tokenization could hide an
+    /// accepted reading of `input` that only exists under a different segmentation.
+    pub fn lookup_all(
+        &self,
+        input: &str,
+        state: FSTState,
+        allow_unknown: bool,
+    ) -> KFSTResult<Vec<(String, f64)>> {
+        self._lookup_all(input, state, allow_unknown)
+    }
+
+    /// Expands the epsilon/symbol-consuming/unknown/identity transitions out of `state` at
+    /// `input_index` into `(successor path weight, successor input index, successor FSTState)`
+    /// triples, for [FST::_run_nbest]'s best-first search. The same transition logic as
+    /// [FST::_run_fst]/[FST::_transition], just yielding successors to a caller-owned queue
+    /// instead of recursing depth-first into every path.
+    fn _nbest_successors(
+        &self,
+        input_symbols: &[Symbol],
+        input_index: usize,
+        state: &FSTState,
+    ) -> Vec<(f64, usize, FSTState)> {
+        let mut result = vec![];
+        let Some(transitions) = self.rules.get(&state.state_num) else {
+            return result;
+        };
+        let isymbol = input_symbols.get(input_index);
+        for transition_isymbol in transitions.keys() {
+            if transition_isymbol.is_epsilon() || isymbol == Some(transition_isymbol) {
+                self._nbest_transition(
+                    input_index,
+                    state,
+                    &transitions[transition_isymbol],
+                    isymbol,
+                    transition_isymbol,
+                    &mut result,
+                );
+            }
+        }
+        if let Some(isymbol) = isymbol {
+            if isymbol.is_unknown() {
+                if let Some(transition_list) = transitions.get(&Symbol::Special(SpecialSymbol::UNKNOWN)) {
+                    self._nbest_transition(
+                        input_index,
+                        state,
+                        transition_list,
+                        Some(isymbol),
+                        &Symbol::Special(SpecialSymbol::UNKNOWN),
+                        &mut result,
+                    );
+                }
+                if let Some(transition_list) = transitions.get(&Symbol::Special(SpecialSymbol::IDENTITY)) {
+                    self._nbest_transition(
+                        input_index,
+                        state,
+                        transition_list,
+                        Some(isymbol),
+                        &Symbol::Special(SpecialSymbol::IDENTITY),
+                        &mut result,
+                    );
+                }
+            }
+        }
+        result
+    }
+
+    fn _nbest_transition(
+        &self,
+        input_index: usize,
+        state: &FSTState,
+        transitions: &[(u64, Symbol, f64)],
+        isymbol: Option<&Symbol>,
+        transition_isymbol: &Symbol,
+        result: &mut Vec<(f64, usize, FSTState)>,
+    ) {
+        for (next_state, osymbol, weight) in transitions.iter() {
+            let new_output_flags = _update_flags(osymbol, &state.output_flags.0);
+            let new_input_flags = _update_flags(transition_isymbol, &state.input_flags.0);
+            let (Some(new_output_flags), Some(new_input_flags)) = (new_output_flags, new_input_flags)
+            else {
+                continue;
+            };
+            let mut new_output_symbols = state.output_symbols.clone();
+            match (isymbol, osymbol) {
+                // Echoes IDENTITY/UNKNOWN the same way [FST::_transition] does.
+                (
+                    Some(isymbol),
+                    Symbol::Special(SpecialSymbol::IDENTITY | SpecialSymbol::UNKNOWN),
+                ) => new_output_symbols.push(isymbol.clone()),
+                _ => {
+                    if !osymbol.is_epsilon() {
+                        new_output_symbols.push(osymbol.clone())
+                    }
+                }
+            };
+            let new_state = FSTState {
+                state_num: *next_state,
+                path_weight: state.path_weight + *weight,
+                input_flags: FlagMap(new_input_flags),
+                output_flags: FlagMap(new_output_flags),
+                output_symbols: new_output_symbols,
+            };
+            let new_index = if transition_isymbol.is_epsilon() {
+                input_index
+            } else {
+                input_index + 1
+            };
+            result.push((new_state.path_weight, new_index, new_state));
+        }
+    }
+
+    /// Best-first (Dijkstra-style) search for the `n` lowest-weight accepting paths, instead of
+    /// [FST::_run_fst]'s exhaustive depth-first enumeration of every path. Pops the
+    /// lowest-accumulated-weight `(input_index, FSTState)` off a priority queue at each step,
+    /// re-queueing a popped state that is final with the input fully consumed as a finished path
+    /// whose weight includes the final weight, emitting each finished path when it is popped in
+    /// turn, and stops as soon as `n` results have been emitted. Since a transducer's epsilon loops would
+    /// otherwise make this non-terminating, a popped entry whose weight is not strictly better
+    /// than the best weight already recorded for its `(state, input_index, flag-state)` key is
+    /// discarded rather than expanded.
+    fn _run_nbest(&self, input_symbols: &[Symbol], state: FSTState, n: usize) -> Vec<(String, f64)> {
+        let mut heap: std::collections::BinaryHeap<NBestEntry> = std::collections::BinaryHeap::new();
+        heap.push(NBestEntry {
+            weight: state.path_weight,
+            input_index: 0,
+            state,
+        });
+        let mut best_seen: std::collections::HashMap<(u64, usize, FlagMap, FlagMap), f64> =
+            std::collections::HashMap::new();
+        let mut results: Vec<(String, f64)> = vec![];
+
+        loop {
+            if results.len() >= n {
+                break;
+            }
+            let Some(entry) = heap.pop() else { break };
+            // An entry past the end of the input is a finished path whose weight already
+            // includes its final weight.
+            if entry.input_index > input_symbols.len() {
+                let output_string: String = entry
+                    .state
+                    .output_symbols
+                    .iter()
+                    .map(Symbol::get_symbol)
+                    .collect::<Vec<String>>()
+                    .join("");
+                results.push((output_string, entry.weight));
+                continue;
+            }
+            let key = (
+                entry.state.state_num,
+                entry.input_index,
+                entry.state.input_flags.clone(),
+                entry.state.output_flags.clone(),
+            );
+            if let Some(&best) = best_seen.get(&key) {
+                if entry.weight >= best {
+                    continue;
+                }
+            }
+            best_seen.insert(key, entry.weight);
+
+            if entry.input_index == input_symbols.len() {
+                if let Some(&final_weight) = self.final_states.get(&entry.state.state_num) {
+                    heap.push(NBestEntry {
+                        weight: entry.weight + final_weight,
+                        input_index: input_symbols.len() + 1,
+                        state: entry.state.clone(),
+                    });
+                }
+            }
+
+            for (successor_weight, successor_index, successor_state) in
+                self._nbest_successors(input_symbols, entry.input_index, &entry.state)
+            {
+                heap.push(NBestEntry {
+                    weight: successor_weight,
+                    input_index: successor_index,
+                    state: successor_state,
+                });
+            }
+        }
+
+        results
+    }
+
+    fn _lookup_nbest(
+        &self,
+        input: &str,
+        state: FSTState,
+        allow_unknown: bool,
+        n: usize,
+    ) -> KFSTResult<Vec<(String, f64)>> {
+        match self.split_to_symbols(input, allow_unknown) {
+            None => {
+                tokenization_exception(format!("Input cannot be split into symbols: {}", input))
+            }
+            Some(input_symbols) => Ok(self._run_nbest(&input_symbols, state, n)),
+        }
+    }
+
+    /// Like [FST::lookup], but returns only the `n` lowest-weight accepting analyses, in
+    /// ascending weight order, found via a weighted best-first search instead of enumerating
+    /// every accepting path and sorting them afterwards. Prefer this over [FST::lookup] when a
+    /// caller only wants the top few analyses of a transducer - like Voikko's - whose accepting
+    /// paths are too numerous (or whose epsilon loops make them unbounded) to enumerate in full.
+    #[cfg(not(feature = "python"))]
+    pub fn lookup_nbest(
+        &self,
+        input: &str,
+        state: FSTState,
+        allow_unknown: bool,
+        n: usize,
+    ) -> KFSTResult<Vec<(String, f64)>> {
+        self._lookup_nbest(input, state, allow_unknown, n)
+    }
+
+    /// [FST::_run_fst]/[FST::_transition]'s traversal, generalized to accumulate weights with an
+    /// arbitrary [Semiring] `S` instead of plain `f64` addition. Tracks flags and output symbols
+    /// as loose locals rather than an [FSTState], since `S` isn't `f64` and so can't live in
+    /// [FSTState::path_weight].
+    fn _run_fst_semiring<S: Semiring>(
+        &self,
+        input_symbols: &[Symbol],
+        state_num: u64,
+        acc: S,
+        input_flags: &im::HashMap<u32, (bool, u32)>,
+        output_flags: &im::HashMap<u32, (bool, u32)>,
+        output_symbols: &[Symbol],
+        outputs: &mut IndexMap<String, S>,
+    ) {
+        let transitions = self.rules.get(&state_num);
+        let isymbol = if input_symbols.is_empty() {
+            if let Some(&weight) = self.final_states.get(&state_num) {
+                let total = acc.times(S::lift(weight));
+                let output_string: String =
+                    output_symbols.iter().map(Symbol::get_symbol).collect::<Vec<_>>().join("");
+                let entry = outputs.entry(output_string).or_insert_with(S::zero);
+                *entry = entry.plus(total);
+            }
+            None
+        } else {
+            Some(&input_symbols[0])
+        };
+        if let Some(transitions) = transitions {
+            for transition_isymbol in transitions.keys() {
+                if transition_isymbol.is_epsilon() || isymbol == Some(transition_isymbol) {
+                    self._transition_semiring(
+                        input_symbols,
+                        acc,
+                        input_flags,
+                        output_flags,
+                        output_symbols,
+                        &transitions[transition_isymbol],
+                        isymbol,
+                        transition_isymbol,
+                        outputs,
+                    );
+                }
+            }
+            if let Some(isymbol) = isymbol {
+                if isymbol.is_unknown() {
+                    if let Some(transition_list) =
+                        transitions.get(&Symbol::Special(SpecialSymbol::UNKNOWN))
+                    {
+                        self._transition_semiring(
+                            input_symbols,
+                            acc,
+                            input_flags,
+                            output_flags,
+                            output_symbols,
+                            transition_list,
+                            Some(isymbol),
+                            &Symbol::Special(SpecialSymbol::UNKNOWN),
+                            outputs,
+                        );
+                    }
+                    if let Some(transition_list) =
+                        transitions.get(&Symbol::Special(SpecialSymbol::IDENTITY))
+                    {
+                        self._transition_semiring(
+                            input_symbols,
+                            acc,
+                            input_flags,
+                            output_flags,
+                            output_symbols,
+                            transition_list,
+                            Some(isymbol),
+                            &Symbol::Special(SpecialSymbol::IDENTITY),
+                            outputs,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// [FST::_transition]'s per-transition body, generalized the same way as
+    /// [FST::_run_fst_semiring].
+    #[allow(clippy::too_many_arguments)]
+    fn _transition_semiring<S: Semiring>(
+        &self,
+        input_symbols: &[Symbol],
+        acc: S,
+        input_flags: &im::HashMap<u32, (bool, u32)>,
+        output_flags: &im::HashMap<u32, (bool, u32)>,
+        output_symbols: &[Symbol],
+        transitions: &[(u64, Symbol, f64)],
+        isymbol: Option<&Symbol>,
+        transition_isymbol: &Symbol,
+        outputs: &mut IndexMap<String, S>,
+    ) {
+        for (next_state, osymbol, weight) in transitions.iter() {
+            let new_output_flags = _update_flags(osymbol, output_flags);
+            let new_input_flags = _update_flags(transition_isymbol, input_flags);
+            match (new_output_flags, new_input_flags) {
+                (Some(new_output_flags), Some(new_input_flags)) => {
+                    let mut new_output_symbols: Vec<Symbol> = output_symbols.to_vec();
+                    match (isymbol, osymbol) {
+                        // Echoes IDENTITY/UNKNOWN the same way [FST::_transition] does.
+                        (
+                            Some(isymbol),
+                            Symbol::Special(SpecialSymbol::IDENTITY | SpecialSymbol::UNKNOWN),
+                        ) => new_output_symbols.push(isymbol.clone()),
+                        _ => {
+                            if !osymbol.is_epsilon() {
+                                new_output_symbols.push(osymbol.clone())
+                            }
+                        }
+                    };
+                    let new_acc = acc.times(S::lift(*weight));
+                    if transition_isymbol.is_epsilon() {
+                        self._run_fst_semiring(
+                            input_symbols,
+                            *next_state,
+                            new_acc,
+                            &new_input_flags,
+                            &new_output_flags,
+                            &new_output_symbols,
+                            outputs,
+                        );
+                    } else {
+                        self._run_fst_semiring(
+                            &input_symbols[1..],
+                            *next_state,
+                            new_acc,
+                            &new_input_flags,
+                            &new_output_flags,
+                            &new_output_symbols,
+                            outputs,
+                        );
+                    }
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    /// Like [FST::lookup], but combines weights with an arbitrary [Semiring] instead of
+    /// hard-coded tropical addition: every accepting path is extended with [Semiring::times], and
+    /// distinct accepting paths that produce the same output string are merged with
+    /// [Semiring::plus]. With `S = `[Tropical] this reports the same per-output weight as
+    /// [FST::lookup] (`plus = min`, so only the best path survives); with `S = `[Probability] it
+    /// instead reports the combined probability mass of every accepting path to that output.
+    ///
+    /// Generic over `S`, so - unlike the rest of [FST]'s public surface - this isn't available
+    /// from Python; pyo3 classes and methods can't be generic. See [FST::to_writer] for the same
+    /// constraint elsewhere in this file.
+    pub fn lookup_semiring<S: Semiring>(
+        &self,
+        input: &str,
+        state: FSTState,
+        allow_unknown: bool,
+    ) -> KFSTResult<Vec<(String, S)>> {
+        match self.split_to_symbols(input, allow_unknown) {
+            None => tokenization_exception(format!("Input cannot be split into symbols: {}", input)),
+            Some(input_symbols) => {
+                let mut outputs: IndexMap<String, S> = IndexMap::new();
+                self._run_fst_semiring(
+                    &input_symbols,
+                    state.state_num,
+                    S::one(),
+                    &state.input_flags.0,
+                    &state.output_flags.0,
+                    &state.output_symbols,
+                    &mut outputs,
+                );
+                Ok(outputs.into_iter().collect())
+            }
+        }
+    }
+
+    /// Composes `self` with `other`, realizing the relation obtained by matching `self`'s output
+    /// symbols against `other`'s input symbols: the result's input alphabet is `self`'s and its
+    /// output alphabet is `other`'s. Running the composed [FST] is equivalent to running `self`
+    /// and feeding every output it produces into `other`, without materializing the intermediate
+    /// string.
+    ///
+    /// States of the result are `(self_state, other_state)` pairs, discovered lazily by a
+    /// breadth-first walk starting from the pair of start states (state `0` in each operand, the
+    /// convention every constructor here follows), and renumbered to fresh `u64` ids in discovery
+    /// order - so the composed [FST]'s start state is again `0`. A pair is final, with the sum of
+    /// both operands' final weights, exactly when both components are.
+    ///
+    /// A third, internal filter bit on every discovered state (see [Self::_intern_compose_state]'s
+    /// key) remembers which side last moved alone on an ε-class symbol (anything for which
+    /// [Symbol::is_epsilon] holds - the literal ε symbol, flag diacritics, ...): after `self` takes
+    /// an output-ε transition alone, `other` may not also take an input-ε transition alone until a
+    /// real symbol match resets the filter, and symmetrically. Without this, the same pair of
+    /// "order doesn't matter" ε moves would be reachable via two different interleavings,
+    /// double-counting that path in the composed transducer.
+    pub fn compose(&self, other: &FST) -> FST {
+        let mut state_ids: IndexMap<(u64, u64, u8), u64> = IndexMap::new();
+        let mut queue: std::collections::VecDeque<(u64, u64, u8)> = std::collections::VecDeque::new();
+        let mut rules: IndexMap<u64, IndexMap<Symbol, Vec<(u64, Symbol, f64)>>> = IndexMap::new();
+        let mut final_states: IndexMap<u64, f64> = IndexMap::new();
+        let mut symbols: HashSet<Symbol> = HashSet::new();
+
+        Self::_intern_compose_state(&mut state_ids, &mut queue, (0, 0, 0));
+
+        while let Some((qa, qb, filter)) = queue.pop_front() {
+            let from_state = state_ids[&(qa, qb, filter)];
+
+            if let (Some(&weight_a), Some(&weight_b)) =
+                (self.final_states.get(&qa), other.final_states.get(&qb))
+            {
+                final_states.insert(from_state, weight_a + weight_b);
+            }
+
+            // Real symbol match: self's output meets other's input on a shared non-ε symbol.
+            // Falling through to the ε-alone case below (`bottom_a.is_epsilon()`) handles self
+            // moving alone on the same arc when no match is possible.
+            if let Some(arcs_a) = self.rules.get(&qa) {
+                for (top_a, transitions_a) in arcs_a.iter() {
+                    for (to_a, bottom_a, weight_a) in transitions_a.iter() {
+                        if !bottom_a.is_epsilon() {
+                            if let Some(arcs_b) =
+                                other.rules.get(&qb).and_then(|m| m.get(bottom_a))
+                            {
+                                for (to_b, bottom_b, weight_b) in arcs_b.iter() {
+                                    Self::_compose_add_transition(
+                                        &mut state_ids,
+                                        &mut queue,
+                                        &mut rules,
+                                        &mut symbols,
+                                        from_state,
+                                        top_a.clone(),
+                                        bottom_b.clone(),
+                                        weight_a + weight_b,
+                                        (*to_a, *to_b, 0),
+                                    );
+                                }
+                            }
+                        } else if filter != 2 {
+                            Self::_compose_add_transition(
+                                &mut state_ids,
+                                &mut queue,
+                                &mut rules,
+                                &mut symbols,
+                                from_state,
+                                top_a.clone(),
+                                bottom_a.clone(),
+                                *weight_a,
+                                (*to_a, qb, 1),
+                            );
+                        }
+                    }
+                }
+            }
+
+            // Other moves alone on an input-ε arc, independent of anything self does.
+            if filter != 1 {
+                if let Some(arcs_b) = other.rules.get(&qb) {
+                    for (top_b, transitions_b) in arcs_b.iter() {
+                        if top_b.is_epsilon() {
+                            for (to_b, bottom_b, weight_b) in transitions_b.iter() {
+                                Self::_compose_add_transition(
+                                    &mut state_ids,
+                                    &mut queue,
+                                    &mut rules,
+                                    &mut symbols,
+                                    from_state,
+                                    top_b.clone(),
+                                    bottom_b.clone(),
+                                    *weight_b,
+                                    (qa, *to_b, 2),
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        FST::_from_rules(final_states, rules, symbols, Some(self.debug))
+    }
+
+    /// Inverts this FST by swapping the input and output symbol of every transition in
+    /// [FST::rules], turning an analysis transducer (surface forms on the input side, tags on the
+    /// output side, as produced by [FST::lookup]) into a generation transducer and vice versa.
+    /// [SpecialSymbol::IDENTITY]/[SpecialSymbol::UNKNOWN] match the same way regardless of which
+    /// side of a transition they sit on (see [FST::_run_fst]), and now echo the matched input
+    /// symbol the same way too (see [FST::_transition]), so swapping the column they occupy
+    /// doesn't change how a transition built around one of them behaves. [RawSymbol]'s
+    /// unknown/identity bits live on the symbol itself, so they're unaffected either way. Flag
+    /// diacritics are always written identically on both sides of a transition (they're ε on
+    /// input and output - see [FlagDiacriticSymbol::is_epsilon]), so swapping them is a no-op.
+    /// [FST::final_states] and the alphabet ([FST::symbols]) are carried over unchanged, since
+    /// neither depends on transition direction.
+    ///
+    /// See [FST::generate] for inverting and running in one step.
+    pub fn invert(&self) -> FST {
+        let mut rules: IndexMap<u64, IndexMap<Symbol, Vec<(u64, Symbol, f64)>>> = IndexMap::new();
+        for (&state, transitions) in self.rules.iter() {
+            for (top, targets) in transitions.iter() {
+                for (to_state, bottom, weight) in targets.iter() {
+                    rules
+                        .entry(state)
+                        .or_default()
+                        .entry(bottom.clone())
+                        .or_default()
+                        .push((*to_state, top.clone(), *weight));
+                }
+            }
+        }
+        FST::_from_rules(
+            self.final_states.clone(),
+            rules,
+            self.symbols.iter().cloned().collect(),
+            Some(self.debug),
+        )
+    }
+
+    /// Turns a tag string (eg. `[Ln][Xp]koivu[X]koivu[Sn][Ny]`) back into surface forms.
+    /// Equivalent to `self.invert().lookup(tags, state, allow_unknown)`, exposed as its own method
+    /// so generation reads as a first-class operation instead of something every caller has to
+    /// remember to invert first. Tokenization of `tags` reuses [FST::split_to_symbols] against
+    /// this (un-inverted) FST's alphabet, which already contains both the surface- and tag-side
+    /// symbols, so no separate tag alphabet needs to be built.
+    pub fn generate(
+        &self,
+        tags: &str,
+        state: FSTState,
+        allow_unknown: bool,
+    ) -> KFSTResult<Vec<(String, f64)>> {
+        self.invert()._lookup(tags, state, allow_unknown)
+    }
+
+    /// Looks up (or allocates, queuing it for the caller to expand) the fresh `u64` id of a
+    /// `(self_state, other_state, filter)` triple discovered during [FST::compose]. Ids are handed
+    /// out in discovery order, so the pair of start states - queued first, by [FST::compose] - is
+    /// always assigned id `0`.
+    fn _intern_compose_state(
+        state_ids: &mut IndexMap<(u64, u64, u8), u64>,
+        queue: &mut std::collections::VecDeque<(u64, u64, u8)>,
+        key: (u64, u64, u8),
+    ) -> u64 {
+        if let Some(&id) = state_ids.get(&key) {
+            return id;
+        }
+        let id = state_ids.len() as u64;
+        state_ids.insert(key, id);
+        queue.push_back(key);
+        id
+    }
+
+    /// Records one transition of the composed [FST] under construction in [FST::compose]:
+    /// interns `target`'s id (queuing it for expansion if it's new), adds `isymbol`/`osymbol` to
+    /// the composed alphabet, and appends the transition to `rules`.
+    #[allow(clippy::too_many_arguments)]
+    fn _compose_add_transition(
+        state_ids: &mut IndexMap<(u64, u64, u8), u64>,
+        queue: &mut std::collections::VecDeque<(u64, u64, u8)>,
+        rules: &mut IndexMap<u64, IndexMap<Symbol, Vec<(u64, Symbol, f64)>>>,
+        symbols: &mut HashSet<Symbol>,
+        from_state: u64,
+        isymbol: Symbol,
+        osymbol: Symbol,
+        weight: f64,
+        target: (u64, u64, u8),
+    ) {
+        let target_id = Self::_intern_compose_state(state_ids, queue, target);
+        symbols.insert(isymbol.clone());
+        symbols.insert(osymbol.clone());
+        rules
+            .entry(from_state)
+            .or_default()
+            .entry(isymbol)
+            .or_default()
+            .push((target_id, osymbol, weight));
+    }
+}
+
+/// One queue entry of [FST::_run_nbest]'s best-first search: an accumulated path weight, how far
+/// into the input it has consumed, and the [FSTState] it reached. Ordered in reverse of `weight`
+/// so that a [std::collections::BinaryHeap] (a max-heap) pops the *lowest*-weight entry first.
+struct NBestEntry {
+    weight: f64,
+    input_index: usize,
+    state: FSTState,
+}
+
+impl PartialEq for NBestEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.weight == other.weight
+    }
+}
+
+impl Eq for NBestEntry {}
+
+impl PartialOrd for NBestEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for NBestEntry {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        other
+            .weight
+            .partial_cmp(&self.weight)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    }
+}
+
+/// Body of [BorrowedFST::split_to_symbols], and the plain scan that [FST::_split_to_symbols]'s
+/// automaton-driven tokenizer is tested against: at each position takes the first symbol, in
+/// the order of `symbols`, whose text is a prefix of the rest of `text`, falling back to
+/// single-character unknown symbols when `allow_unknown` is set.
+fn _split_text_to_symbols(symbols: &[Symbol], text: &str, allow_unknown: bool) -> Option<Vec<Symbol>> {
+    let mut result = vec![];
+    let mut pos = text.chars();
+    'outer: while pos.size_hint().0 > 0 {
+        for symbol in symbols.iter() {
+            let symbol_string = symbol.get_symbol();
+            if pos.as_str().starts_with(&symbol_string) {
+                result.push(symbol.clone());
+                // Consume correct amount of characters from iterator
+                for _ in symbol_string.chars() {
+                    pos.next();
+                }
+                continue 'outer;
+            }
+        }
+        if allow_unknown {
+            result.push(Symbol::String(StringSymbol {
+                string: intern(pos.next().unwrap().to_string()),
+                unknown: true,
+            }));
+        } else {
+            return None;
+        }
+    }
+    Some(result)
+}
+
+/// An Aho-Corasick automaton over an [FST]'s alphabet ([FST::symbols]), built once by
+/// [SymbolAutomaton::build] and cached on [FST::automaton]. Drives [FST::split_to_symbols] and
+/// [FST::split_to_symbols_all] in time linear in the length of the tokenized text (plus the
+/// number of alphabet symbols that actually occur in it), instead of [_split_text_to_symbols]'s
+/// `text_len * symbols.len()` scan.
+struct SymbolAutomaton {
+    /// `goto[state]` holds the explicit trie edges out of `state`; a character with no entry
+    /// falls through to `fail[state]` (see [SymbolAutomaton::step]).
+    goto: Vec<IndexMap<char, usize>>,
+    /// `fail[state]` is the state to fall back to when `goto[state]` has no edge for the current
+    /// character - the longest proper suffix of `state`'s path that is itself a trie prefix.
+    fail: Vec<usize>,
+    /// `output[state]` lists every alphabet symbol whose text ends exactly at `state`, including
+    /// those inherited through `fail` links from shorter suffixes (so matching "abc" also
+    /// reports "bc" and "c" if those are themselves symbols).
+    output: Vec<Vec<Symbol>>,
+    /// The position of each symbol in the [FST::symbols] list the automaton was built from, used
+    /// to break ties between several symbols matching at the same position the same way
+    /// [_split_text_to_symbols]'s linear scan would: by taking whichever comes first in
+    /// [FST::symbols].
+    rank: IndexMap<Symbol, usize>,
+}
+
+impl SymbolAutomaton {
+    /// Builds the trie (`goto`), then computes `fail` and `output` with a breadth-first walk, in
+    /// the standard Aho-Corasick construction.
+    fn build(symbols: &[Symbol]) -> SymbolAutomaton {
+        let mut goto: Vec<IndexMap<char, usize>> = vec![IndexMap::new()];
+        let mut output: Vec<Vec<Symbol>> = vec![vec![]];
+        let mut rank: IndexMap<Symbol, usize> = IndexMap::new();
+
+        for (i, symbol) in symbols.iter().enumerate() {
+            rank.insert(symbol.clone(), i);
+            let mut state = 0;
+            for c in symbol.get_symbol().chars() {
+                state = match goto[state].get(&c) {
+                    Some(&next) => next,
+                    None => {
+                        goto.push(IndexMap::new());
+                        output.push(vec![]);
+                        let next = goto.len() - 1;
+                        goto[state].insert(c, next);
+                        next
+                    }
+                };
+            }
+            output[state].push(symbol.clone());
+        }
+
+        let mut fail: Vec<usize> = vec![0; goto.len()];
+        let mut queue: std::collections::VecDeque<usize> = std::collections::VecDeque::new();
+        for &child in goto[0].values() {
+            fail[child] = 0;
+            queue.push_back(child);
+        }
+        while let Some(state) = queue.pop_front() {
+            let children: Vec<(char, usize)> =
+                goto[state].iter().map(|(&c, &child)| (c, child)).collect();
+            for (c, child) in children {
+                queue.push_back(child);
+                let mut fallback = fail[state];
+                while fallback != 0 && !goto[fallback].contains_key(&c) {
+                    fallback = fail[fallback];
+                }
+                fail[child] = goto[fallback].get(&c).copied().filter(|&s| s != child).unwrap_or(0);
+                let inherited = output[fail[child]].clone();
+                output[child].extend(inherited);
+            }
+        }
+
+        SymbolAutomaton { goto, fail, output, rank }
+    }
+
+    /// Advances `state` by one character, following `fail` links until a `goto` edge for `c` is
+    /// found (or the root is reached).
+    fn step(&self, mut state: usize, c: char) -> usize {
+        while state != 0 && !self.goto[state].contains_key(&c) {
+            state = self.fail[state];
+        }
+        self.goto[state].get(&c).copied().unwrap_or(0)
+    }
+
+    /// Runs the automaton over `chars` once, returning for every start position the `(end,
+    /// symbol)` pairs of every alphabet symbol that occurs starting exactly there - the edges of
+    /// the segmentation DAG that [FST::split_to_symbols]/[FST::split_to_symbols_all] walk.
+    fn match_edges(&self, chars: &[char]) -> Vec<Vec<(usize, Symbol)>> {
+        let mut edges_by_start: Vec<Vec<(usize, Symbol)>> = vec![vec![]; chars.len()];
+        let mut state = 0;
+        for (i, &c) in chars.iter().enumerate() {
+            state = self.step(state, c);
+            for symbol in &self.output[state] {
+                let len = symbol.get_symbol().chars().count();
+                if len <= i + 1 {
+                    edges_by_start[i + 1 - len].push((i + 1, symbol.clone()));
+                }
+            }
+        }
+        edges_by_start
+    }
+}
+
+/// A borrowed, lazily-decoded view of a KFST transition table, returned by
+/// [FST::from_kfst_bytes_borrowed]. [FST::from_kfst_bytes] decodes every transition into an owned
+/// `(u64, Symbol, f64)` triple up front, which is wasteful for a transducer the size of
+/// `voikko.kfst`; `BorrowedFST` instead keeps the transition table as an index of byte offsets
+/// into the source buffer ([BorrowedFST::rule_index]) and decodes a transition's
+/// `(to_state, bottom_symbol, weight)` only when [BorrowedFST::run_fst] actually visits it.
+/// Exposes the same `lookup`/`run_fst`/`split_to_symbols` surface as [FST], but its lifetime is
+/// tied to the buffer it borrows from - see [mmap_kfst_file] (behind the `mmap`
+/// feature) for pairing it with a memory-mapped file.
+pub struct BorrowedFST<'a> {
+    final_states: IndexMap<u64, f64>,
+    symbols: Vec<Symbol>,
+    version: u16,
+    is_weighted: bool,
+    /// Maps a state, then a top symbol out of that state, to the byte offsets (within
+    /// [BorrowedFST::transitions]) of each matching transition's encoded
+    /// `(from_state, to_state, top_symbol_idx, bottom_symbol_idx, weight?)` entry.
+    rule_index: IndexMap<u64, IndexMap<Symbol, Vec<usize>>>,
+    /// The decompressed (but not yet decoded) transition table, immediately followed by the
+    /// final-state table. Borrowed straight from the source buffer when that buffer was already
+    /// uncompressed, owned otherwise - see [FST::from_kfst_bytes_borrowed].
+    transitions: Cow<'a, [u8]>,
+}
+
+impl<'a> BorrowedFST<'a> {
+    /// Decodes the `(to_state, bottom_symbol, weight)` triple stored at `offset` in
+    /// [BorrowedFST::transitions]. `offset` always comes from [BorrowedFST::rule_index], so it is
+    /// trusted to point at a complete, well-formed entry - [FST::from_kfst_bytes_borrowed] is the
+    /// only thing that ever populates it, and only with offsets it has already validated.
+    fn _decode_transition_at(&self, offset: usize) -> (u64, Symbol, f64) {
+        let entry = &self.transitions[offset..];
+        let (entry, _from_state) = nom::number::complete::be_u32::<&[u8], ()>
+            .parse(entry)
+            .expect("rule_index offset did not point at a valid transition entry");
+        let (entry, to_state) = nom::number::complete::be_u32::<&[u8], ()>
+            .parse(entry)
+            .expect("rule_index offset did not point at a valid transition entry");
+        let (entry, _top_symbol_idx) = self
+            ._parse_symbol_idx(entry)
+            .expect("rule_index offset did not point at a valid transition entry");
+        let (entry, bottom_symbol_idx) = self
+            ._parse_symbol_idx(entry)
+            .expect("rule_index offset did not point at a valid transition entry");
+        let weight = if self.is_weighted {
+            let (_, weight) = nom::number::complete::be_f64::<&[u8], ()>
+                .parse(entry)
+                .expect("rule_index offset did not point at a valid transition entry");
+            weight
+        } else {
+            0.0
+        };
+        (to_state.into(), self.symbols[bottom_symbol_idx].clone(), weight)
+    }
+
+    /// Reads a symbol-table index of whichever width [FST::_to_kfst_parts] (format v0 vs v1)
+    /// wrote it at, widening the result to `usize` right away (see the identical technique in
+    /// [FST::_finish_from_kfst_parts]).
+    fn _parse_symbol_idx<'b>(&self, input: &'b [u8]) -> nom::IResult<&'b [u8], usize, ()> {
+        if self.version == 0 {
+            let (rest, idx) = nom::number::complete::be_u16::<&[u8], ()>.parse(input)?;
+            Ok((rest, idx.into()))
+        } else {
+            let (rest, idx) = nom::number::complete::be_u32::<&[u8], ()>.parse(input)?;
+            Ok((rest, idx as usize))
+        }
+    }
+
+    fn _decode_offsets(&self, offsets: &[usize]) -> Vec<(u64, Symbol, f64)> {
+        offsets
+            .iter()
+            .map(|&offset| self._decode_transition_at(offset))
+            .collect()
+    }
+
+    fn _run_fst(
+        &self,
+        input_symbols: &[Symbol],
+        state: &FSTState,
+        post_input_advance: bool,
+        result: &mut Vec<(bool, bool, FSTState)>,
+    ) {
+        let transitions = self.rule_index.get(&state.state_num);
+        let isymbol = if input_symbols.is_empty() {
+            match self.final_states.get(&state.state_num) {
+                Some(&weight) => {
+                    result.push((
+                        true,
+                        post_input_advance,
+                        FSTState {
+                            state_num: state.state_num,
+                            path_weight: state.path_weight + weight,
+                            input_flags: state.input_flags.clone(),
+                            output_flags: state.output_flags.clone(),
+                            output_symbols: state.output_symbols.clone(),
+                        },
+                    ));
+                }
+                None => {
+                    result.push((false, post_input_advance, state.clone()));
+                }
+            }
+            None
+        } else {
+            Some(&input_symbols[0])
+        };
+        if let Some(transitions) = transitions {
+            for transition_isymbol in transitions.keys() {
+                if transition_isymbol.is_epsilon() || isymbol == Some(transition_isymbol) {
+                    let decoded = self._decode_offsets(&transitions[transition_isymbol]);
+                    self._transition(input_symbols, state, &decoded, isymbol, transition_isymbol, result);
+                }
+            }
+            if let Some(isymbol) = isymbol {
+                if isymbol.is_unknown() {
+                    if let Some(offsets) = transitions.get(&Symbol::Special(SpecialSymbol::UNKNOWN)) {
+                        let decoded = self._decode_offsets(offsets);
+                        self._transition(
+                            input_symbols,
+                            state,
+                            &decoded,
+                            Some(isymbol),
+                            &Symbol::Special(SpecialSymbol::UNKNOWN),
+                            result,
+                        );
+                    }
+
+                    if let Some(offsets) = transitions.get(&Symbol::Special(SpecialSymbol::IDENTITY)) {
+                        let decoded = self._decode_offsets(offsets);
+                        self._transition(
+                            input_symbols,
+                            state,
+                            &decoded,
+                            Some(isymbol),
+                            &Symbol::Special(SpecialSymbol::IDENTITY),
+                            result,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn _transition(
+        &self,
+        input_symbols: &[Symbol],
+        state: &FSTState,
+        transitions: &[(u64, Symbol, f64)],
+        isymbol: Option<&Symbol>,
+        transition_isymbol: &Symbol,
+        result: &mut Vec<(bool, bool, FSTState)>,
+    ) {
+        for (next_state, osymbol, weight) in transitions.iter() {
+            let new_output_flags = _update_flags(osymbol, &state.output_flags.0);
+            let new_input_flags = _update_flags(transition_isymbol, &state.input_flags.0);
+
+            match (new_output_flags, new_input_flags) {
+                (Some(new_output_flags), Some(new_input_flags)) => {
+                    let mut new_output_symbols: Vec<Symbol> = state.output_symbols.clone();
+                    match (isymbol, osymbol) {
+                        // Echoes IDENTITY/UNKNOWN the same way [FST::_transition] does.
+                        (
+                            Some(isymbol),
+                            Symbol::Special(SpecialSymbol::IDENTITY | SpecialSymbol::UNKNOWN),
+                        ) => new_output_symbols.push(isymbol.clone()),
+                        _ => {
+                            if !osymbol.is_epsilon() {
+                                new_output_symbols.push(osymbol.clone())
+                            }
+                        }
+                    };
+                    let new_state = FSTState {
+                        state_num: *next_state,
+                        path_weight: state.path_weight + *weight,
+                        input_flags: FlagMap(new_input_flags),
+                        output_flags: FlagMap(new_output_flags),
+                        output_symbols: new_output_symbols,
+                    };
+                    if transition_isymbol.is_epsilon() {
+                        self._run_fst(input_symbols, &new_state, input_symbols.is_empty(), result);
+                    } else {
+                        let cloned_symbols = &input_symbols[1..];
+                        self._run_fst(cloned_symbols, &new_state, false, result);
+                    }
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    /// Same contract as [FST::run_fst], but decodes each visited transition's
+    /// `(to_state, bottom_symbol, weight)` out of [BorrowedFST::transitions] on demand instead of
+    /// reading it out of an owned rule map.
+    pub fn run_fst(
+        &self,
+        input_symbols: Vec<Symbol>,
+        state: FSTState,
+        post_input_advance: bool,
+    ) -> Vec<(bool, bool, FSTState)> {
+        let mut result = vec![];
+        self._run_fst(input_symbols.as_slice(), &state, post_input_advance, &mut result);
+        result
+    }
+
+    /// See [FST::split_to_symbols].
+    pub fn split_to_symbols(&self, text: &str, allow_unknown: bool) -> Option<Vec<Symbol>> {
+        _split_text_to_symbols(&self.symbols, text, allow_unknown)
+    }
+
+    /// See [FST::lookup].
+    pub fn lookup(
+        &self,
+        input: &str,
+        state: FSTState,
+        allow_unknown: bool,
+    ) -> KFSTResult<Vec<(String, f64)>> {
+        let input_symbols = self.split_to_symbols(input, allow_unknown);
+        match input_symbols {
+            None => {
+                tokenization_exception(format!("Input cannot be split into symbols: {}", input))
+            }
+            Some(input_symbols) => {
+                let mut dedup: IndexSet<String> = IndexSet::new();
+                let mut result: Vec<(String, f64)> = vec![];
+                let mut finished_paths: Vec<_> = self
+                    .run_fst(input_symbols.clone(), state, false)
+                    .into_iter()
+                    .filter(|(finished, _, _)| *finished)
+                    .collect();
+                finished_paths
+                    .sort_by(|a, b| a.2.path_weight.partial_cmp(&b.2.path_weight).unwrap());
+                for finished in finished_paths {
+                    let output_string: String = finished
+                        .2
+                        .output_symbols
+                        .iter()
+                        .map(|x| x.get_symbol())
+                        .collect::<Vec<String>>()
+                        .join("");
+                    if dedup.contains(&output_string) {
+                        continue;
+                    }
+                    dedup.insert(output_string.clone());
+                    result.push((output_string, finished.2.path_weight));
+                }
+                Ok(result)
+            }
+        }
+    }
+}
+
+/// Builds a `kfstpack` container: a typed, length-prefixed, self-describing bundle of one or
+/// more named [FST]s plus a free-form metadata record, modelled on netencode's tagged-record
+/// layout (see the module-level binary format this mirrors in [FST::from_kfst_bytes]). A reader
+/// that doesn't understand a record's tag can still skip straight past it using the `u32` length
+/// every record carries, so the format can grow new record kinds without breaking old readers.
+///
+/// ```
+/// use kfst_rs::{FST, PackBuilder};
+///
+/// let a = FST::from_att_code("0\t1\tc\tc\n1".to_string(), false).unwrap();
+/// let b = FST::from_att_code("0\t1\td\td\n1".to_string(), false).unwrap();
+/// let bytes = PackBuilder::new()
+///     .add_fst("a", &a).unwrap()
+///     .add_fst("b", &b).unwrap()
+///     .set_metadata("language", "fi")
+///     .build();
+///
+/// assert_eq!(FST::pack_names(&bytes).unwrap(), vec!["a".to_string(), "b".to_string()]);
+/// let loaded_b = FST::from_pack_bytes(&bytes, "b").unwrap();
+/// assert_eq!(loaded_b.to_att_code(), b.to_att_code());
+/// ```
+#[derive(Default)]
+pub struct PackBuilder {
+    ffsts: Vec<(String, Vec<u8>)>,
+    metadata: IndexMap<String, String>,
+}
+
+impl PackBuilder {
+    /// Starts an empty pack with no embedded transducers and no metadata.
+    pub fn new() -> Self {
+        PackBuilder::default()
+    }
+
+    /// Appends `fst`, serialized via [FST::to_kfst_bytes], under `name`. Appending a second FST
+    /// under a name already used is allowed (the container then has two [PACK_RECORD_FST]
+    /// entries with that name); [FST::from_pack_bytes] returns whichever one it encounters first.
+    pub fn add_fst(&mut self, name: impl Into<String>, fst: &FST) -> KFSTResult<&mut Self> {
+        let bytes = fst.to_kfst_bytes()?;
+        self.ffsts.push((name.into(), bytes));
+        Ok(self)
+    }
+
+    /// Sets a metadata key (language, weight interpretation, creation tool, ...) to `value`,
+    /// overwriting any value already set for that key in this builder.
+    pub fn set_metadata(&mut self, key: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// Serializes the accumulated FSTs and metadata into a `kfstpack` byte buffer: the `"KPAK"`
+    /// magic tag, a `u16` format version (currently always `0`), then one [PACK_RECORD_FST]
+    /// record per [PackBuilder::add_fst] call in order, followed by a single
+    /// [PACK_RECORD_METADATA] record if any metadata was set.
+    pub fn build(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(PACK_MAGIC);
+        out.extend_from_slice(&0u16.to_be_bytes());
+
+        for (name, kfst_bytes) in &self.ffsts {
+            let name_bytes = name.as_bytes();
+            let mut payload = Vec::with_capacity(4 + name_bytes.len() + kfst_bytes.len());
+            payload.extend((name_bytes.len() as u32).to_be_bytes());
+            payload.extend_from_slice(name_bytes);
+            payload.extend_from_slice(kfst_bytes);
+            out.push(PACK_RECORD_FST);
+            out.extend((payload.len() as u32).to_be_bytes());
+            out.extend(payload);
+        }
+
+        if !self.metadata.is_empty() {
+            let mut payload = Vec::new();
+            payload.extend((self.metadata.len() as u32).to_be_bytes());
+            for (key, value) in &self.metadata {
+                payload.extend((key.len() as u32).to_be_bytes());
+                payload.extend_from_slice(key.as_bytes());
+                payload.extend((value.len() as u32).to_be_bytes());
+                payload.extend_from_slice(value.as_bytes());
+            }
+            out.push(PACK_RECORD_METADATA);
+            out.extend((payload.len() as u32).to_be_bytes());
+            out.extend(payload);
+        }
+
+        out
+    }
+}
+
+fn _update_flags(
+    symbol: &Symbol,
+    flags: &im::HashMap<u32, (bool, u32)>,
+) -> Option<im::HashMap<u32, (bool, u32)>> {
+    if let Symbol::Flag(flag_diacritic_symbol) = symbol {
+        _apply_flag_to_map(flag_diacritic_symbol, flags)
+    } else {
+        Some(flags.clone())
+    }
 }
 
-fn _update_flags(
-    symbol: &Symbol,
+/// Apply a single flag diacritic to a flag map, implementing the full HFST P/N/R/D/C/U
+/// semantics. Returns [None] if the flag's condition is not met (the transition it is
+/// attached to may not be taken), otherwise a new map in which only `flag_diacritic_symbol.key`
+/// may have changed.
+fn _apply_flag_to_map(
+    flag_diacritic_symbol: &FlagDiacriticSymbol,
     flags: &im::HashMap<u32, (bool, u32)>,
 ) -> Option<im::HashMap<u32, (bool, u32)>> {
-    if let Symbol::Flag(flag_diacritic_symbol) = symbol {
-        match flag_diacritic_symbol.flag_type {
-            FlagDiacriticType::U => {
-                let value = flag_diacritic_symbol.value;
+    match flag_diacritic_symbol.flag_type {
+        FlagDiacriticType::U => {
+            let value = flag_diacritic_symbol.value;
 
-                // Is the current state somehow in conflict?
-                // It can be, if we are negatively set to what we try to unify to or we are positively set to sth else
+            // Is the current state somehow in conflict?
+            // It can be, if we are negatively set to what we try to unify to or we are positively set to sth else
 
-                if let Some((currently_set, current_value)) = flags.get(&flag_diacritic_symbol.key)
+            if let Some((currently_set, current_value)) = flags.get(&flag_diacritic_symbol.key) {
+                if (*currently_set && current_value != &value)
+                    || (!currently_set && current_value == &value)
                 {
-                    if (*currently_set && current_value != &value)
-                        || (!currently_set && current_value == &value)
-                    {
-                        return None;
-                    }
+                    return None;
                 }
+            }
 
-                // Otherwise, update flag set
+            // Otherwise, update flag set
 
-                let mut clone: im::HashMap<u32, (bool, u32)> = flags.clone();
-                clone.insert(flag_diacritic_symbol.key, (true, value));
-                Some(clone)
-            }
-            FlagDiacriticType::R => {
-                // Param count matters
+            let mut clone: im::HashMap<u32, (bool, u32)> = flags.clone();
+            clone.insert(flag_diacritic_symbol.key, (true, value));
+            Some(clone)
+        }
+        FlagDiacriticType::R => {
+            // Param count matters
 
-                match flag_diacritic_symbol.value {
-                    u32::MAX => {
-                        if flags.contains_key(&flag_diacritic_symbol.key) {
-                            Some(flags.clone())
-                        } else {
-                            None
-                        }
+            match flag_diacritic_symbol.value {
+                u32::MAX => {
+                    if flags.contains_key(&flag_diacritic_symbol.key) {
+                        Some(flags.clone())
+                    } else {
+                        None
                     }
-                    value => {
-                        if flags
-                            .get(&flag_diacritic_symbol.key)
-                            .map(|stored| _test_flag(stored, value))
-                            .unwrap_or(false)
-                        {
-                            Some(flags.clone())
-                        } else {
-                            None
-                        }
+                }
+                value => {
+                    if flags
+                        .get(&flag_diacritic_symbol.key)
+                        .map(|stored| _test_flag(stored, value))
+                        .unwrap_or(false)
+                    {
+                        Some(flags.clone())
+                    } else {
+                        None
                     }
                 }
             }
-            FlagDiacriticType::D => {
-                match (
-                    flag_diacritic_symbol.value,
-                    flags.get(&flag_diacritic_symbol.key),
-                ) {
-                    (u32::MAX, None) => Some(flags.clone()),
-                    (u32::MAX, _) => None,
-                    (_, None) => Some(flags.clone()),
-                    (query, Some(stored)) => {
-                        if _test_flag(stored, query) {
-                            None
-                        } else {
-                            Some(flags.clone())
-                        }
+        }
+        FlagDiacriticType::D => {
+            match (
+                flag_diacritic_symbol.value,
+                flags.get(&flag_diacritic_symbol.key),
+            ) {
+                (u32::MAX, None) => Some(flags.clone()),
+                (u32::MAX, _) => None,
+                (_, None) => Some(flags.clone()),
+                (query, Some(stored)) => {
+                    if _test_flag(stored, query) {
+                        None
+                    } else {
+                        Some(flags.clone())
                     }
                 }
             }
-            FlagDiacriticType::C => {
-                let mut flag_clone = flags.clone();
-                flag_clone.remove(&flag_diacritic_symbol.key);
-                Some(flag_clone)
-            }
-            FlagDiacriticType::P => {
-                let value = flag_diacritic_symbol.value;
-                let mut flag_clone = flags.clone();
-                flag_clone.insert(flag_diacritic_symbol.key, (true, value));
-                Some(flag_clone)
-            }
-            FlagDiacriticType::N => {
-                let value = flag_diacritic_symbol.value;
-                let mut flag_clone = flags.clone();
-                flag_clone.insert(flag_diacritic_symbol.key, (false, value));
-                Some(flag_clone)
-            }
         }
-    } else {
-        Some(flags.clone())
+        FlagDiacriticType::C => {
+            let mut flag_clone = flags.clone();
+            flag_clone.remove(&flag_diacritic_symbol.key);
+            Some(flag_clone)
+        }
+        FlagDiacriticType::P => {
+            let value = flag_diacritic_symbol.value;
+            let mut flag_clone = flags.clone();
+            flag_clone.insert(flag_diacritic_symbol.key, (true, value));
+            Some(flag_clone)
+        }
+        FlagDiacriticType::N => {
+            let value = flag_diacritic_symbol.value;
+            let mut flag_clone = flags.clone();
+            flag_clone.insert(flag_diacritic_symbol.key, (false, value));
+            Some(flag_clone)
+        }
     }
 }
 
@@ -2362,6 +4882,68 @@ impl FST {
         rows.join("\n")
     }
 
+    /// Like [FST::to_att_code], but with final states sorted numerically and transitions sorted
+    /// by `(from_state, top_symbol, bottom_symbol, to_state, weight)` using [Symbol]'s total
+    /// order, and `-0.0` weights normalized to `0.0`. Two FSTs that are equal as graphs produce
+    /// identical output regardless of the insertion order of their [FST::rules]/
+    /// [FST::final_states] maps, so `from_att_code(x.to_att_code_canonical())` round-trips
+    /// byte-for-byte. See also [FST::to_kfst_bytes_canonical].
+    pub fn to_att_code_canonical(&self) -> String {
+        let mut final_states: Vec<(u64, f64)> = self
+            .final_states
+            .iter()
+            .map(|(&state, &weight)| (state, if weight == 0.0 { 0.0 } else { weight }))
+            .collect();
+        final_states.sort_by_key(|&(state, _)| state);
+
+        let mut transitions: Vec<(u64, Symbol, u64, Symbol, f64)> = self
+            .rules
+            .iter()
+            .flat_map(|(&from_state, rules)| {
+                rules.iter().flat_map(move |(top_symbol, transitions)| {
+                    transitions.iter().map(move |&(to_state, ref bottom_symbol, weight)| {
+                        (
+                            from_state,
+                            top_symbol.clone(),
+                            to_state,
+                            bottom_symbol.clone(),
+                            if weight == 0.0 { 0.0 } else { weight },
+                        )
+                    })
+                })
+            })
+            .collect();
+        FST::_sort_canonical_transitions(&mut transitions);
+
+        let mut rows: Vec<String> = vec![];
+        for (state, weight) in final_states {
+            match weight {
+                0.0 => rows.push(format!("{}", state)),
+                _ => rows.push(format!("{}\t{}", state, weight)),
+            }
+        }
+        for (from_state, top_symbol, to_state, bottom_symbol, weight) in transitions {
+            match weight {
+                0.0 => rows.push(format!(
+                    "{}\t{}\t{}\t{}",
+                    from_state,
+                    to_state,
+                    top_symbol.get_symbol(),
+                    bottom_symbol.get_symbol()
+                )),
+                _ => rows.push(format!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    from_state,
+                    to_state,
+                    top_symbol.get_symbol(),
+                    bottom_symbol.get_symbol(),
+                    weight
+                )),
+            }
+        }
+        rows.join("\n")
+    }
+
     #[cfg(feature = "python")]
     #[staticmethod]
     #[pyo3(signature = (kfst_file, debug = false))]
@@ -2376,6 +4958,26 @@ impl FST {
         FST::__from_kfst_bytes(kfst_bytes, debug)
     }
 
+    #[cfg(feature = "python")]
+    #[staticmethod]
+    fn sniff_format(bytes: &[u8]) -> FstFormat {
+        FST::_sniff_format(bytes)
+    }
+
+    #[cfg(feature = "python")]
+    #[staticmethod]
+    #[pyo3(signature = (bytes, debug = false))]
+    fn from_bytes(bytes: &[u8], debug: bool) -> KFSTResult<FST> {
+        FST::_from_bytes(bytes, debug)
+    }
+
+    #[cfg(feature = "python")]
+    #[staticmethod]
+    #[pyo3(signature = (path, debug = false))]
+    fn from_file(py: Python<'_>, path: PyObject, debug: bool) -> KFSTResult<FST> {
+        FST::_from_file(path.call_method0(py, "__str__")?.extract(py)?, debug)
+    }
+
     #[cfg(feature = "python")]
     pub fn to_kfst_file(&self, py: Python<'_>, kfst_file: PyObject) -> KFSTResult<()> {
         let bytes = self.to_kfst_bytes()?;
@@ -2395,10 +4997,58 @@ impl FST {
     }
 
     /// Serialize the current transducer to a bytestring in the KFST format. See [FST::from_kfst_bytes] for more details on the KFST format.
+    /// Always uses [Compression::Xz]; see [FST::to_kfst_bytes_with] to pick a different codec.
     pub fn to_kfst_bytes(&self) -> KFSTResult<Vec<u8>> {
-        match self._to_kfst_bytes() {
+        FST::_to_kfst_bytes_with(self, Compression::Xz, false)
+    }
+
+    #[cfg(feature = "python")]
+    #[pyo3(name = "to_kfst_bytes_with", signature = (codec = Compression::Xz))]
+    fn py_to_kfst_bytes_with(&self, codec: Compression) -> KFSTResult<Vec<u8>> {
+        FST::_to_kfst_bytes_with(self, codec, false)
+    }
+
+    /// Like [FST::to_kfst_bytes], but lets the caller pick the codec that compresses the
+    /// transition-table payload. [Compression::None] skips compression entirely, trading file
+    /// size for faster loading (or for a dump that's easy to inspect with other tools).
+    #[cfg(not(feature = "python"))]
+    pub fn to_kfst_bytes_with(&self, codec: Compression) -> KFSTResult<Vec<u8>> {
+        FST::_to_kfst_bytes_with(self, codec, false)
+    }
+
+    /// Like [FST::to_kfst_bytes], but with the transition table and final-state list written in
+    /// canonical order (see [FST::to_att_code_canonical]) and `-0.0` weights normalized to `0.0`,
+    /// so that `from_kfst_bytes(x.to_kfst_bytes_canonical())` round-trips byte-for-byte and two
+    /// FSTs that are equal as graphs produce identical bytes regardless of insertion order.
+    /// Always uses [Compression::Xz].
+    pub fn to_kfst_bytes_canonical(&self) -> KFSTResult<Vec<u8>> {
+        FST::_to_kfst_bytes_with(self, Compression::Xz, true)
+    }
+
+    #[cfg(feature = "python")]
+    #[staticmethod]
+    fn from_pack_bytes(pack_bytes: &[u8], name: &str) -> KFSTResult<FST> {
+        match FST::_from_pack_bytes(pack_bytes, name) {
             Ok(x) => Ok(x),
-            Err(x) => value_error(x),
+            Err(x) => parse_error(x),
+        }
+    }
+
+    #[cfg(feature = "python")]
+    #[staticmethod]
+    fn pack_names(pack_bytes: &[u8]) -> KFSTResult<Vec<String>> {
+        match FST::_pack_names(pack_bytes) {
+            Ok(x) => Ok(x),
+            Err(x) => parse_error(x),
+        }
+    }
+
+    #[cfg(feature = "python")]
+    #[staticmethod]
+    fn pack_metadata(pack_bytes: &[u8]) -> KFSTResult<IndexMap<String, String>> {
+        match FST::_pack_metadata(pack_bytes) {
+            Ok(x) => Ok(x),
+            Err(x) => parse_error(x),
         }
     }
 
@@ -2417,6 +5067,12 @@ impl FST {
         self._split_to_symbols(text, allow_unknown)
     }
 
+    #[cfg(feature = "python")]
+    #[pyo3(signature = (text, allow_unknown = true))]
+    fn split_to_symbols_all(&self, text: &str, allow_unknown: bool) -> Option<Vec<Vec<Symbol>>> {
+        self._split_to_symbols_all(text, allow_unknown)
+    }
+
     #[cfg(feature = "python")]
     #[pyo3(signature = (input_symbols, state = FSTState::_new(0), post_input_advance = false))]
     fn run_fst(
@@ -2439,6 +5095,29 @@ impl FST {
         self._lookup(input, state, allow_unknown)
     }
 
+    #[cfg(feature = "python")]
+    #[pyo3(signature = (input, state=FSTState::_new(0), allow_unknown=true))]
+    fn lookup_all(
+        &self,
+        input: &str,
+        state: FSTState,
+        allow_unknown: bool,
+    ) -> KFSTResult<Vec<(String, f64)>> {
+        self._lookup_all(input, state, allow_unknown)
+    }
+
+    #[cfg(feature = "python")]
+    #[pyo3(name = "lookup_nbest", signature = (input, n, state=FSTState::_new(0), allow_unknown=true))]
+    fn py_lookup_nbest(
+        &self,
+        input: &str,
+        n: usize,
+        state: FSTState,
+        allow_unknown: bool,
+    ) -> KFSTResult<Vec<(String, f64)>> {
+        self._lookup_nbest(input, state, allow_unknown, n)
+    }
+
     #[deprecated]
     /// Equal to:
     /// ```no_test
@@ -2678,6 +5357,48 @@ fn test_minimal_r_diacritic() {
     );
 }
 
+#[test]
+fn test_apply_flag_semantics() {
+    let p = FlagDiacriticSymbol::new("P".to_string(), "X".to_string(), Some("1".to_string())).unwrap();
+    let n = FlagDiacriticSymbol::new("N".to_string(), "X".to_string(), Some("1".to_string())).unwrap();
+    let c = FlagDiacriticSymbol::new("C".to_string(), "X".to_string(), None).unwrap();
+    let r_set = FlagDiacriticSymbol::new("R".to_string(), "X".to_string(), None).unwrap();
+    let r_match = FlagDiacriticSymbol::new("R".to_string(), "X".to_string(), Some("1".to_string())).unwrap();
+    let r_mismatch = FlagDiacriticSymbol::new("R".to_string(), "X".to_string(), Some("2".to_string())).unwrap();
+    let d_mismatch = FlagDiacriticSymbol::new("D".to_string(), "X".to_string(), Some("2".to_string())).unwrap();
+    let d_match = FlagDiacriticSymbol::new("D".to_string(), "X".to_string(), Some("1".to_string())).unwrap();
+    let u_same = FlagDiacriticSymbol::new("U".to_string(), "X".to_string(), Some("1".to_string())).unwrap();
+    let u_other = FlagDiacriticSymbol::new("U".to_string(), "X".to_string(), Some("2".to_string())).unwrap();
+
+    let start = FSTState::_new(0);
+
+    // R requires the flag to already be set; P sets it.
+    assert!(start.apply_flag(&r_set).is_none());
+    let after_p = start.apply_flag(&p).unwrap();
+    assert!(after_p.apply_flag(&r_match).is_some());
+    assert!(after_p.apply_flag(&r_mismatch).is_none());
+    assert!(after_p.apply_flag(&d_mismatch).is_some());
+    assert!(after_p.apply_flag(&d_match).is_none());
+
+    // U unifies with an equal positive value and conflicts with a different one.
+    assert!(after_p.apply_flag(&u_same).is_some());
+    assert!(after_p.apply_flag(&u_other).is_none());
+
+    // N sets the flag negatively; U then disagrees with the same value but accepts a different one.
+    let after_n = start.apply_flag(&n).unwrap();
+    assert!(after_n.apply_flag(&u_same).is_none());
+    assert!(after_n.apply_flag(&u_other).is_some());
+
+    // C clears the flag, after which R without a value fails again.
+    let after_c = after_p.apply_flag(&c).unwrap();
+    assert!(after_c.apply_flag(&r_set).is_none());
+
+    // apply_flag never touches weight, output flags or output symbols.
+    assert_eq!(after_p.path_weight, start.path_weight);
+    assert_eq!(after_p.output_flags, start.output_flags);
+    assert_eq!(after_p.output_symbols, start.output_symbols);
+}
+
 #[test]
 fn test_kfst_voikko_lentää_result_count() {
     let fst = FST::_from_kfst_file("../pyvoikko/pyvoikko/voikko.kfst".to_string(), false).unwrap();
@@ -2887,6 +5608,360 @@ fn test_simple_identity() {
     );
 }
 
+#[test]
+fn test_split_to_symbols_matches_naive_algorithm_under_ambiguity() {
+    // "ab" can be tokenized either as the single symbol "ab" or as "a" followed by "b"; both
+    // readings reach the (sole) final state 1, so the alphabet is genuinely ambiguous.
+    let code = "0\t1\tab\tab\n0\t2\ta\ta\n2\t1\tb\tb\n1";
+    let fst = FST::from_att_code(code.to_string(), false).unwrap();
+
+    assert_eq!(
+        fst.split_to_symbols("ab", true),
+        _split_text_to_symbols(&fst.symbols, "ab", true),
+    );
+}
+
+#[test]
+fn test_split_to_symbols_all_enumerates_every_tokenization() {
+    let code = "0\t1\tab\tab\n0\t2\ta\ta\n2\t1\tb\tb\n1";
+    let fst = FST::from_att_code(code.to_string(), false).unwrap();
+
+    let mut all = fst.split_to_symbols_all("ab", true).unwrap();
+    all.sort_by_key(|segmentation| segmentation.len());
+    assert_eq!(
+        all,
+        vec![
+            vec![Symbol::parse("ab").unwrap().1],
+            vec![Symbol::parse("a").unwrap().1, Symbol::parse("b").unwrap().1],
+        ]
+    );
+
+    // Both segmentations reach the same final state and produce the same output, so lookup_all
+    // should report it once, same as the single-segmentation lookup.
+    assert_eq!(
+        fst.lookup_all("ab", FSTState::_new(0), true).unwrap(),
+        fst.lookup("ab", FSTState::_new(0), true).unwrap(),
+    );
+}
+
+#[test]
+fn test_lookup_nbest_returns_lowest_weight_paths_in_ascending_order() {
+    // Three distinct accepting paths for "a" (through three different final states, so none of
+    // them collide on the (state, input_index, flag-state) pruning key), with weights 2.0, 0.5
+    // and 1.0 - the 2-best should be [0.5, 1.0].
+    let att_code = "0\t1\ta\tx\t2.0\n\
+                     0\t2\ta\ty\t0.5\n\
+                     0\t3\ta\tz\t1.0\n\
+                     1\n2\n3";
+    let fst = FST::from_att_code(att_code.to_string(), false).unwrap();
+
+    let nbest = fst.lookup_nbest("a", FSTState::_new(0), true, 2).unwrap();
+    assert_eq!(
+        nbest,
+        vec![("y".to_string(), 0.5), ("z".to_string(), 1.0)]
+    );
+
+    // The full exhaustive lookup, sorted and truncated to 2, should agree with lookup_nbest.
+    let mut exhaustive = fst.lookup("a", FSTState::_new(0), true).unwrap();
+    exhaustive.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+    assert_eq!(nbest, exhaustive[..2]);
+}
+
+#[test]
+fn test_lookup_nbest_terminates_with_an_epsilon_loop() {
+    // State 1 has a zero-weight epsilon self-loop, which would make an exhaustive path
+    // enumeration (or a naive unguarded best-first search) loop forever.
+    let att_code = "0\t1\ta\tb\n\
+                     1\t1\t@_EPSILON_SYMBOL_@\t@_EPSILON_SYMBOL_@\t1.0\n\
+                     1";
+    let fst = FST::from_att_code(att_code.to_string(), false).unwrap();
+
+    let nbest = fst.lookup_nbest("a", FSTState::_new(0), true, 3).unwrap();
+    assert_eq!(nbest, vec![("b".to_string(), 0.0)]);
+}
+
+#[test]
+fn test_lookup_semiring_tropical_matches_plain_lookup() {
+    // Two distinct accepting paths both produce "x"; Tropical's plus = min should agree with
+    // plain lookup(), which keeps only the lowest-weight occurrence of each output.
+    let att_code = "0\t1\ta\tx\t2.0\n\
+                     0\t2\ta\tx\t0.5\n\
+                     1\n2";
+    let fst = FST::from_att_code(att_code.to_string(), false).unwrap();
+
+    let semiring = fst
+        .lookup_semiring::<Tropical>("a", FSTState::_new(0), true)
+        .unwrap();
+    assert_eq!(semiring, vec![("x".to_string(), Tropical(0.5))]);
+
+    let plain = fst.lookup("a", FSTState::_new(0), true).unwrap();
+    assert_eq!(plain, vec![("x".to_string(), 0.5)]);
+}
+
+#[test]
+fn test_lookup_semiring_probability_sums_path_probabilities() {
+    // Same two paths as above, but Probability's plus = + should report the *combined*
+    // probability mass of both paths (e^-2.0 + e^-0.5), not just the cheaper one.
+    let att_code = "0\t1\ta\tx\t2.0\n\
+                     0\t2\ta\tx\t0.5\n\
+                     1\n2";
+    let fst = FST::from_att_code(att_code.to_string(), false).unwrap();
+
+    let semiring = fst
+        .lookup_semiring::<Probability>("a", FSTState::_new(0), true)
+        .unwrap();
+    let expected = (-2.0_f64).exp() + (-0.5_f64).exp();
+    assert_eq!(semiring.len(), 1);
+    assert_eq!(semiring[0].0, "x");
+    assert!((semiring[0].1.0 - expected).abs() < 1e-12);
+}
+
+#[test]
+fn test_compose_chains_two_transducers_and_sums_weights() {
+    // self: "a" -> "b" (weight 1.0); other: "b" -> "c" (weight 2.0).
+    let fst_a = FST::from_att_code("0\t1\ta\tb\t1.0\n1".to_string(), false).unwrap();
+    let fst_b = FST::from_att_code("0\t1\tb\tc\t2.0\n1".to_string(), false).unwrap();
+
+    let composed = fst_a.compose(&fst_b);
+    let result = composed.lookup("a", FSTState::_new(0), true).unwrap();
+    assert_eq!(result, vec![("c".to_string(), 3.0)]);
+}
+
+#[test]
+fn test_compose_epsilon_filter_does_not_double_count() {
+    // self consumes "a" and becomes final by itself, without ever producing a real output
+    // symbol (its only transition's output is ε) - this only reaches other's epsilon filter
+    // via the "self moves alone" rule. other is final at its own start state without
+    // consuming anything, so the composed result must see exactly one accepting path.
+    let fst_a =
+        FST::from_att_code("0\t1\ta\t@_EPSILON_SYMBOL_@\t0.5\n1".to_string(), false).unwrap();
+    let fst_b = FST::from_att_code("0\t0.3".to_string(), false).unwrap();
+
+    let composed = fst_a.compose(&fst_b);
+    let result = composed.lookup("a", FSTState::_new(0), true).unwrap();
+    assert_eq!(result, vec![("".to_string(), 0.8)]);
+}
+
+#[test]
+fn test_invert_swaps_input_and_output_of_every_transition() {
+    let fst = FST::from_att_code("0\t1\ta\tb\t1.0\n1".to_string(), false).unwrap();
+
+    let inverted = fst.invert();
+    assert_eq!(
+        inverted.lookup("b", FSTState::_new(0), true).unwrap(),
+        vec![("a".to_string(), 1.0)]
+    );
+    // Inverting twice gets back the original relation.
+    assert_eq!(
+        inverted.invert().lookup("a", FSTState::_new(0), true).unwrap(),
+        fst.lookup("a", FSTState::_new(0), true).unwrap()
+    );
+}
+
+#[test]
+fn test_invert_preserves_flag_diacritics_and_identity() {
+    // A flag diacritic (identical on both sides) followed by an identity transition.
+    let code = "0\t1\t@P.TEST.1@\t@P.TEST.1@\n\
+                1\t2\t@_IDENTITY_SYMBOL_@\t@_IDENTITY_SYMBOL_@\t0.5\n\
+                2";
+    let fst = FST::from_att_code(code.to_string(), false).unwrap();
+
+    let inverted = fst.invert();
+    assert_eq!(
+        inverted.lookup("x", FSTState::_new(0), true).unwrap(),
+        vec![("x".to_string(), 0.5)]
+    );
+}
+
+#[test]
+fn test_invert_echoes_unknown_transitions_instead_of_a_literal_placeholder() {
+    // Same transducer as test_simple_unknown: UNKNOWN only matches genuinely unknown input
+    // chars and collapses them all to the literal output "y".
+    let fst = FST::from_att_code("0\t1\t@_UNKNOWN_SYMBOL_@\ty\n1".to_string(), false).unwrap();
+    assert_eq!(
+        fst.lookup("x", FSTState::_new(0), true).unwrap(),
+        vec![("y".to_string(), 0.0)]
+    );
+
+    // Inverting swaps top and bottom, so UNKNOWN now sits on the output side. Without the
+    // IDENTITY/UNKNOWN echo symmetry in [FST::_transition], this would be written out as the
+    // literal "@_UNKNOWN_SYMBOL_@" placeholder instead of the matched input symbol.
+    let inverted = fst.invert();
+    assert_eq!(
+        inverted.lookup("y", FSTState::_new(0), true).unwrap(),
+        vec![("y".to_string(), 0.0)]
+    );
+    assert_eq!(
+        fst.generate("y", FSTState::_new(0), true).unwrap(),
+        inverted.lookup("y", FSTState::_new(0), true).unwrap()
+    );
+}
+
+#[test]
+fn test_generate_turns_tags_back_into_surface_forms() {
+    let fst = FST::from_att_code("0\t1\tb\tt\t1.0\n1".to_string(), false).unwrap();
+
+    assert_eq!(
+        fst.generate("t", FSTState::_new(0), true).unwrap(),
+        vec![("b".to_string(), 1.0)]
+    );
+    // generate() mirrors lookup() on the inverted FST.
+    assert_eq!(
+        fst.generate("t", FSTState::_new(0), true).unwrap(),
+        fst.invert().lookup("t", FSTState::_new(0), true).unwrap()
+    );
+}
+
+#[test]
+fn test_from_bytes_sniffs_format() {
+    let att_code = "0\t1\tc\tc\n1";
+    assert_eq!(FST::sniff_format(att_code.as_bytes()), FstFormat::Att);
+    let from_att = FST::from_bytes(att_code.as_bytes(), false).unwrap();
+    assert_eq!(from_att.to_att_code(), FST::from_att_code(att_code.to_string(), false).unwrap().to_att_code());
+
+    let kfst_bytes = from_att.to_kfst_bytes().unwrap();
+    assert_eq!(FST::sniff_format(&kfst_bytes), FstFormat::Kfst);
+    let from_kfst = FST::from_bytes(&kfst_bytes, false).unwrap();
+    assert_eq!(from_kfst.to_att_code(), from_att.to_att_code());
+
+    assert!(FST::from_bytes(&[0xff, 0xfe, 0xfd], false).is_err());
+}
+
+#[test]
+fn test_to_writer_from_reader_round_trip() {
+    let att_code = "0\t1\tc\tc\n1";
+    let fst = FST::from_att_code(att_code.to_string(), false).unwrap();
+
+    let mut written = Vec::new();
+    fst.to_writer(&mut written).unwrap();
+    assert_eq!(written, fst.to_kfst_bytes().unwrap());
+
+    let read_back = FST::from_reader(written.as_slice(), false).unwrap();
+    assert_eq!(read_back.to_att_code(), fst.to_att_code());
+}
+
+#[test]
+fn test_kfst_v1_round_trips_many_symbols() {
+    // KFST format v0 stores the symbol count and per-transition symbol indices as u16, capping
+    // the symbol alphabet at 65535 entries. Format v1 widens both to u32; it should be picked
+    // automatically once the alphabet overflows u16, and read back correctly either way.
+    let num_symbols = (u16::MAX as usize) + 2;
+    let mut att_code = String::new();
+    for i in 0..num_symbols {
+        att_code.push_str(&format!("{}\t{}\ts{}\ts{}\n", i, i + 1, i, i));
+    }
+    att_code.push_str(&num_symbols.to_string());
+    let fst = FST::from_att_code(att_code, false).unwrap();
+    assert!(fst.symbols.len() > u16::MAX as usize);
+
+    let kfst_bytes = fst.to_kfst_bytes().unwrap();
+    assert_eq!(&kfst_bytes[4..6], &1u16.to_be_bytes()); // version 1
+
+    let from_bytes = FST::from_kfst_bytes(&kfst_bytes, false).unwrap();
+    assert_eq!(from_bytes.to_att_code(), fst.to_att_code());
+
+    let mut written = Vec::new();
+    fst.to_writer(&mut written).unwrap();
+    assert_eq!(written, kfst_bytes);
+    let from_reader = FST::from_reader(written.as_slice(), false).unwrap();
+    assert_eq!(from_reader.to_att_code(), fst.to_att_code());
+}
+
+#[test]
+fn test_to_kfst_bytes_with_uncompressed_codec_round_trips() {
+    let att_code = "0\t1\tc\tc\n1\t2\td\td\t0.5\n2";
+    let fst = FST::from_att_code(att_code.to_string(), false).unwrap();
+
+    let xz_bytes = fst.to_kfst_bytes_with(Compression::Xz).unwrap();
+    assert_eq!(xz_bytes, fst.to_kfst_bytes().unwrap()); // Compression::Xz is the implicit default
+
+    let raw_bytes = fst.to_kfst_bytes_with(Compression::None).unwrap();
+    assert_ne!(raw_bytes, xz_bytes);
+    let from_raw = FST::from_kfst_bytes(&raw_bytes, false).unwrap();
+    assert_eq!(from_raw.to_att_code(), fst.to_att_code());
+
+    // A header byte with only the legacy is-weighted bit set (no codec bit) must still decode as
+    // xz, since every file written before Compression existed was implicitly xz-compressed.
+    assert_eq!(
+        FST::_decode_weighted_and_codec(0),
+        (false, Compression::Xz)
+    );
+    assert_eq!(FST::_decode_weighted_and_codec(1), (true, Compression::Xz));
+}
+
+#[test]
+fn test_from_kfst_bytes_borrowed_round_trips() {
+    let att_code = "0\t1\tc\tc\n1\t2\td\td\t0.5\n2";
+    let fst = FST::from_att_code(att_code.to_string(), false).unwrap();
+
+    // Compression::None is the only codec from_kfst_bytes_borrowed can borrow the payload from
+    // directly; Compression::Xz must still decode correctly, just via an owned decompression.
+    for codec in [Compression::None, Compression::Xz] {
+        let kfst_bytes = fst.to_kfst_bytes_with(codec).unwrap();
+        let borrowed = FST::from_kfst_bytes_borrowed(&kfst_bytes).unwrap();
+
+        for input in ["cd", "c"] {
+            assert_eq!(
+                borrowed.lookup(input, FSTState::_new(0), true).unwrap(),
+                fst.lookup(input, FSTState::_new(0), true).unwrap(),
+            );
+        }
+        assert_eq!(
+            borrowed.split_to_symbols("cd", true),
+            fst._split_to_symbols("cd", true),
+        );
+    }
+}
+
+#[test]
+fn test_canonical_serialization_is_independent_of_insertion_order() {
+    // Two transducers that are structurally equal (same states, transitions and weights) but
+    // built up by feeding their rules to from_att_code in a different order - insertion order
+    // into the resulting rules/final_states maps differs, but the canonical serializations
+    // shouldn't.
+    let att_code_a = "0\t1\tc\tc\n1\t2\td\td\t0.5\n0\t2\te\te\n2";
+    let att_code_b = "0\t2\te\te\n1\t2\td\td\t0.5\n0\t1\tc\tc\n2";
+    let fst_a = FST::from_att_code(att_code_a.to_string(), false).unwrap();
+    let fst_b = FST::from_att_code(att_code_b.to_string(), false).unwrap();
+    assert_ne!(fst_a.to_att_code(), fst_b.to_att_code());
+
+    assert_eq!(fst_a.to_att_code_canonical(), fst_b.to_att_code_canonical());
+    assert_eq!(
+        fst_a.to_kfst_bytes_canonical().unwrap(),
+        fst_b.to_kfst_bytes_canonical().unwrap()
+    );
+
+    let round_tripped = FST::from_att_code(fst_a.to_att_code_canonical(), false).unwrap();
+    assert_eq!(
+        round_tripped.to_att_code_canonical(),
+        fst_a.to_att_code_canonical()
+    );
+    let from_kfst = FST::from_kfst_bytes(&fst_a.to_kfst_bytes_canonical().unwrap(), false).unwrap();
+    assert_eq!(
+        from_kfst.to_kfst_bytes_canonical().unwrap(),
+        fst_a.to_kfst_bytes_canonical().unwrap()
+    );
+}
+
+#[test]
+fn test_to_att_code_round_trips_special_symbols() {
+    // Epsilon, identity, unknown and a flag diacritic all need their escaped @..@ surface form
+    // preserved exactly by to_att_code, since from_att_code re-parses it through Symbol::parse.
+    let code = "0\t1\t@_EPSILON_SYMBOL_@\t@_IDENTITY_SYMBOL_@\n\
+                1\t2\t@_UNKNOWN_SYMBOL_@\ty\t0.5\n\
+                2\t3\t@P.TEST.1@\t@P.TEST.1@\n\
+                3";
+    let fst = FST::from_att_code(code.to_string(), false).unwrap();
+    let round_tripped = FST::from_att_code(fst.to_att_code(), false).unwrap();
+
+    for input in ["x", "y"] {
+        assert_eq!(
+            fst.lookup(input, FSTState::_new(0), true),
+            round_tripped.lookup(input, FSTState::_new(0), true),
+        );
+    }
+}
+
 #[test]
 fn test_raw_symbols() {
     // Construct simple transducer
@@ -2922,6 +5997,7 @@ fn test_raw_symbols() {
         rules,
         symbols,
         debug: false,
+        automaton: OnceLock::new(),
     };
 
     // Accepting example that tests epsilon + unknown bits
@@ -2965,9 +6041,63 @@ fn test_raw_symbols() {
     );
 }
 
+#[test]
+fn test_pack_builder_round_trips_multiple_ffsts_and_metadata() {
+    let fst_a = FST::from_att_code("0\t1\tc\tc\n1".to_string(), false).unwrap();
+    let fst_b = FST::from_att_code("0\t1\td\td\t0.5\n1".to_string(), false).unwrap();
+
+    let mut builder = PackBuilder::new();
+    builder.add_fst("analyzer", &fst_a).unwrap();
+    builder.add_fst("generator", &fst_b).unwrap();
+    builder.set_metadata("language", "fi");
+    builder.set_metadata("tool", "kfst-rs");
+    let bytes = builder.build();
+
+    assert_eq!(
+        FST::pack_names(&bytes).unwrap(),
+        vec!["analyzer".to_string(), "generator".to_string()]
+    );
+
+    let loaded_a = FST::from_pack_bytes(&bytes, "analyzer").unwrap();
+    assert_eq!(loaded_a.to_att_code(), fst_a.to_att_code());
+    let loaded_b = FST::from_pack_bytes(&bytes, "generator").unwrap();
+    assert_eq!(loaded_b.to_att_code(), fst_b.to_att_code());
+
+    let metadata = FST::pack_metadata(&bytes).unwrap();
+    assert_eq!(metadata.get("language"), Some(&"fi".to_string()));
+    assert_eq!(metadata.get("tool"), Some(&"kfst-rs".to_string()));
+}
+
+#[test]
+fn test_pack_metadata_last_entry_wins_on_duplicate_key() {
+    let mut builder = PackBuilder::new();
+    builder.set_metadata("language", "fi");
+    builder.set_metadata("language", "sv"); // Overwrites the earlier value for the same key.
+    let bytes = builder.build();
+
+    let metadata = FST::pack_metadata(&bytes).unwrap();
+    assert_eq!(metadata.get("language"), Some(&"sv".to_string()));
+    assert_eq!(metadata.len(), 1);
+}
+
+#[test]
+fn test_from_pack_bytes_reports_missing_name() {
+    let fst = FST::from_att_code("0\t1\tc\tc\n1".to_string(), false).unwrap();
+    let mut builder = PackBuilder::new();
+    builder.add_fst("only", &fst).unwrap();
+    let bytes = builder.build();
+
+    assert!(FST::from_pack_bytes(&bytes, "missing").is_err());
+    assert!(FST::from_kfst_bytes(&fst.to_kfst_bytes().unwrap(), false).is_ok());
+}
+
 /// A Python module implemented in Rust.
+///
+/// Declares `gil_used = false`: nothing in this module relies on the GIL for synchronization
+/// (the symbol interner is independently sharded, see [intern]/[deintern]), so it is safe to
+/// import on free-threaded (no-GIL) CPython builds without forcing the GIL back on.
 #[cfg(feature = "python")]
-#[pymodule]
+#[pymodule(gil_used = false)]
 fn kfst_rs(py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
     let symbols = PyModule::new(m.py(), "symbols")?;
     symbols.add_class::<StringSymbol>()?;
@@ -2992,6 +6122,17 @@ fn kfst_rs(py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
         "TokenizationException",
         py.get_type::<TokenizationException>(),
     )?;
+    transducer.add("KfstError", py.get_type::<KfstError>())?;
+    transducer.add("BadFlagDiacriticError", py.get_type::<BadFlagDiacriticError>())?;
+    transducer.add("MalformedAttRowError", py.get_type::<MalformedAttRowError>())?;
+    transducer.add(
+        "TruncatedKfstHeaderError",
+        py.get_type::<TruncatedKfstHeaderError>(),
+    )?;
+    transducer.add(
+        "SymbolTableMismatchError",
+        py.get_type::<SymbolTableMismatchError>(),
+    )?;
 
     py_run!(
         py,
@@ -3007,6 +6148,7 @@ fn kfst_rs(py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
         "TokenizationException",
         py.get_type::<TokenizationException>(),
     )?;
+    m.add("KfstError", py.get_type::<KfstError>())?;
     m.add_class::<FST>()?;
 
     Ok(())
@@ -0,0 +1,56 @@
+/*
+ This file is part of KFST.
+
+ (c) 2023-2025 Iikka Hauhio <iikka.hauhio@helsinki.fi> and Théo Salmenkivi-Friberg <theo.friberg@helsinki.fi>
+
+ KFST is free software: you can redistribute it and/or modify it under the
+ terms of the GNU Lesser General Public License as published by the Free
+ Software Foundation, either version 3 of the License, or (at your option) any
+ later version.
+
+ KFST is distributed in the hope that it will be useful, but WITHOUT ANY
+ WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more
+ details.
+
+ You should have received a copy of the GNU Lesser General Public License
+ along with KFST. If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! A thin `memmap2`-backed opener to pair with [crate::FST::from_kfst_bytes_borrowed]. This
+//! module doesn't wrap the mapped file and the [BorrowedFST](crate::BorrowedFST) borrowing from
+//! it into a single self-referential type: a [memmap2::Mmap] already derefs to `&[u8]`, so the
+//! caller just keeps the `Mmap` alive for as long as they use the `BorrowedFST` built from it.
+//! Build with `--features mmap`.
+
+use std::fs::File;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::{io_error, KFSTResult};
+
+/// Memory-maps the KFST file at `path` read-only, for use with
+/// [FST::from_kfst_bytes_borrowed](crate::FST::from_kfst_bytes_borrowed):
+///
+/// ```no_test
+/// let mapped = mmap_kfst_file(path)?;
+/// let fst = FST::from_kfst_bytes_borrowed(&mapped)?;
+/// ```
+///
+/// # Safety
+///
+/// Memory-mapping a file is only sound so long as nothing truncates or otherwise mutates it out
+/// from under the mapping for as long as the returned [Mmap] (and anything borrowing from it,
+/// like a [BorrowedFST](crate::BorrowedFST)) is alive; `memmap2` itself can't enforce this, so
+/// callers are responsible for not pointing this at a file something else might write to.
+pub fn mmap_kfst_file(path: String) -> KFSTResult<Mmap> {
+    let file = match File::open(Path::new(&path)) {
+        Ok(file) => file,
+        Err(err) => return io_error(format!("Failed to open file {}:\n{}", path, err)),
+    };
+    match unsafe { Mmap::map(&file) } {
+        Ok(mapped) => Ok(mapped),
+        Err(err) => io_error(format!("Failed to memory-map file {}:\n{}", path, err)),
+    }
+}
@@ -0,0 +1,157 @@
+/*
+ This file is part of KFST.
+
+ (c) 2023-2025 Iikka Hauhio <iikka.hauhio@helsinki.fi> and Théo Salmenkivi-Friberg <theo.friberg@helsinki.fi>
+
+ KFST is free software: you can redistribute it and/or modify it under the
+ terms of the GNU Lesser General Public License as published by the Free
+ Software Foundation, either version 3 of the License, or (at your option) any
+ later version.
+
+ KFST is distributed in the hope that it will be useful, but WITHOUT ANY
+ WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more
+ details.
+
+ You should have received a copy of the GNU Lesser General Public License
+ along with KFST. If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! An interactive REPL for exploring a loaded [FST]. Grammar authors trying to understand why a
+//! flag diacritic silently blocked a path they expected to match get little help from [FST::lookup]
+//! alone, since it only ever returns the finished output strings: this module instead walks every
+//! path (finished or not) and prints its weight, output symbols and evolving [FlagMap] flag state,
+//! using the crate-private [deintern] to turn the interned flag names/values back into text. Build
+//! with `--features repl` and run the `kfst_repl` binary.
+
+use std::io::{self, BufRead, Write};
+
+use crate::{deintern, FSTState, Symbol, FST};
+
+/// Runs the REPL against an already-loaded transducer, reading commands from `input` and writing
+/// output to `output` until `:quit` or end of input. See the [repl](self) module docs.
+pub fn run_repl(fst: &FST, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    let mut show_epsilons = false;
+    let mut lines = input.lines();
+
+    writeln!(
+        output,
+        "kfst REPL. Type an input string to look it up, or :help for commands."
+    )?;
+    loop {
+        write!(output, "> ")?;
+        output.flush()?;
+        let Some(line) = lines.next() else {
+            return Ok(());
+        };
+        let mut line = line?;
+
+        // Multi-line entry: a trailing backslash continues the input onto the next prompt.
+        while line.ends_with('\\') {
+            line.pop();
+            write!(output, "..> ")?;
+            output.flush()?;
+            match lines.next() {
+                Some(next) => line.push_str(&next?),
+                None => break,
+            }
+        }
+
+        match line.trim() {
+            ":quit" | ":exit" => return Ok(()),
+            ":help" => print_help(&mut output)?,
+            ":flags" => {
+                show_epsilons = !show_epsilons;
+                writeln!(
+                    output,
+                    "Showing epsilon/flag symbols in output traces: {}",
+                    show_epsilons
+                )?;
+            }
+            line if line.starts_with(":symbols ") => {
+                print_symbols(&line[":symbols ".len()..], &mut output)?;
+            }
+            "" => {}
+            line => lookup(fst, line, show_epsilons, &mut output)?,
+        }
+    }
+}
+
+fn print_help(output: &mut impl Write) -> io::Result<()> {
+    writeln!(output, "Commands:")?;
+    writeln!(output, "  <text>          Look up <text> and show every accepted path")?;
+    writeln!(output, "  :symbols <text> Show how <text> parses into Symbols, token by token")?;
+    writeln!(
+        output,
+        "  :flags          Toggle showing epsilon/flag symbols in output traces (default: off)"
+    )?;
+    writeln!(output, "  :help           Show this message")?;
+    writeln!(output, "  :quit           Exit the REPL")?;
+    writeln!(
+        output,
+        "A line ending in '\\' continues onto the next prompt, for long inputs."
+    )
+}
+
+fn print_symbols(text: &str, output: &mut impl Write) -> io::Result<()> {
+    for token in text.split_whitespace() {
+        match crate::from_symbol_string(token) {
+            Some(symbol) => writeln!(output, "  {:?} -> {:?}", token, symbol)?,
+            None => writeln!(output, "  {:?} -> <failed to parse>", token)?,
+        }
+    }
+    Ok(())
+}
+
+fn lookup(fst: &FST, input: &str, show_epsilons: bool, output: &mut impl Write) -> io::Result<()> {
+    let Some(input_symbols) = fst._split_to_symbols(input, true) else {
+        writeln!(output, "Input cannot be split into symbols: {:?}", input)?;
+        return Ok(());
+    };
+    let mut paths = fst.__run_fst(input_symbols, FSTState::default(), false);
+    paths.sort_by(|a, b| a.2.path_weight.partial_cmp(&b.2.path_weight).unwrap());
+
+    let mut any_finished = false;
+    for (finished, _, state) in &paths {
+        if !finished {
+            continue;
+        }
+        any_finished = true;
+        let output_string = trace_string(&state.output_symbols, show_epsilons);
+        writeln!(output, "{} (weight {})", output_string, state.path_weight)?;
+        writeln!(output, "  input_flags:  {}", flag_map_string(&state.input_flags.0))?;
+        writeln!(output, "  output_flags: {}", flag_map_string(&state.output_flags.0))?;
+    }
+    if !any_finished {
+        writeln!(output, "No accepted path.")?;
+    }
+    Ok(())
+}
+
+fn trace_string(symbols: &[Symbol], show_epsilons: bool) -> String {
+    symbols
+        .iter()
+        .filter(|symbol| show_epsilons || !symbol.is_epsilon())
+        .map(Symbol::get_symbol)
+        .collect::<Vec<_>>()
+        .join("")
+}
+
+fn flag_map_string(flags: &im::HashMap<u32, (bool, u32)>) -> String {
+    if flags.is_empty() {
+        return "(none)".to_string();
+    }
+    let mut entries: Vec<String> = flags
+        .iter()
+        .map(|(&key, &(positive, value))| {
+            let sign = if positive { '+' } else { '-' };
+            if value == u32::MAX {
+                format!("{}{}", sign, deintern(key))
+            } else {
+                format!("{}{}.{}", sign, deintern(key), deintern(value))
+            }
+        })
+        .collect();
+    entries.sort();
+    entries.join(", ")
+}
@@ -0,0 +1,375 @@
+/*
+ This file is part of KFST.
+
+ (c) 2023-2025 Iikka Hauhio <iikka.hauhio@helsinki.fi> and Théo Salmenkivi-Friberg <theo.friberg@helsinki.fi>
+
+ KFST is free software: you can redistribute it and/or modify it under the
+ terms of the GNU Lesser General Public License as published by the Free
+ Software Foundation, either version 3 of the License, or (at your option) any
+ later version.
+
+ KFST is distributed in the hope that it will be useful, but WITHOUT ANY
+ WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ FOR A PARTICULAR PURPOSE. See the GNU Lesser General Public License for more
+ details.
+
+ You should have received a copy of the GNU Lesser General Public License
+ along with KFST. If not, see <https://www.gnu.org/licenses/>.
+*/
+
+//! Kotlin/Swift (and, via the community `uniffi-bindgen-ruby` generator, Ruby) bindings for the
+//! core transducer engine, built on [uniffi] instead of pyo3.
+//!
+//! uniffi and pyo3 disagree about ownership (uniffi objects are handed out as `Arc<Self>`; pyo3
+//! classes are plain values GIL-managed by Python) and about errors (uniffi needs a `[uniffi::Error]`
+//! enum with only FFI-safe field types; pyo3 wants a `PyErr`). Rather than bending [FST], [FSTState]
+//! and friends to satisfy both at once, this module is a thin adapter layer: it wraps the
+//! binding-neutral core types in `Arc`-held handles and re-maps [KfstError] onto [UniffiError], the
+//! same way the `python` feature's `value_error`/`io_error`/`parse_error` helpers re-map it onto
+//! `PyErr`. Build with `--features uniffi` to generate the scaffolding; see the uniffi docs for
+//! running `uniffi-bindgen` against the resulting cdylib to produce Kotlin/Swift sources, or
+//! `uniffi-bindgen-ruby` for Ruby.
+
+use std::sync::Arc;
+
+use crate::{
+    deintern, FlagDiacriticSymbol, FlagDiacriticType, FstParseErrorKind, FSTState, KfstError,
+    SpecialSymbol, StringSymbol, Symbol, FST,
+};
+
+/// The uniffi-visible counterpart of [FstParseErrorKind]; same variants, just derived for uniffi
+/// instead of relying on `#[cfg_attr]`-stacking the same derive onto the core enum, since the core
+/// enum is shared with the `python` build where a second, unrelated derive isn't needed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum UniffiParseErrorKind {
+    /// See [FstParseErrorKind::BadFlagDiacritic].
+    BadFlagDiacritic,
+    /// See [FstParseErrorKind::MalformedAttRow].
+    MalformedAttRow,
+    /// See [FstParseErrorKind::TruncatedKfstHeader].
+    TruncatedKfstHeader,
+    /// See [FstParseErrorKind::SymbolTableMismatch].
+    SymbolTableMismatch,
+}
+
+impl From<FstParseErrorKind> for UniffiParseErrorKind {
+    fn from(kind: FstParseErrorKind) -> Self {
+        match kind {
+            FstParseErrorKind::BadFlagDiacritic => UniffiParseErrorKind::BadFlagDiacritic,
+            FstParseErrorKind::MalformedAttRow => UniffiParseErrorKind::MalformedAttRow,
+            FstParseErrorKind::TruncatedKfstHeader => UniffiParseErrorKind::TruncatedKfstHeader,
+            FstParseErrorKind::SymbolTableMismatch => UniffiParseErrorKind::SymbolTableMismatch,
+        }
+    }
+}
+
+/// The uniffi-visible counterpart of [KfstError]. uniffi errors can't carry a [FstParseError]
+/// field directly (its `offset`/`line`/`column` are `usize`, which uniffi doesn't lower), so the
+/// `Parse` variant is flattened into FFI-safe fields instead.
+#[derive(Debug, Clone, uniffi::Error)]
+pub enum UniffiError {
+    /// A failure carried only as a message (file I/O and other errors without a position);
+    /// see [KfstError::Io].
+    Io { message: String },
+    /// Failed to parse ATT/KFST data or a symbol; see [KfstError::Parse].
+    Parse {
+        kind: UniffiParseErrorKind,
+        offset: u64,
+        line: u64,
+        column: u64,
+        token: String,
+    },
+}
+
+impl std::fmt::Display for UniffiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UniffiError::Io { message } => write!(f, "{}", message),
+            UniffiError::Parse {
+                kind,
+                offset,
+                line,
+                column,
+                token,
+            } => write!(
+                f,
+                "{:?} at line {}, column {} (byte offset {}): {:?}",
+                kind, line, column, offset, token
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UniffiError {}
+
+impl From<KfstError> for UniffiError {
+    fn from(err: KfstError) -> Self {
+        match err {
+            KfstError::Io(message) => UniffiError::Io { message },
+            KfstError::Parse(err) => UniffiError::Parse {
+                kind: err.kind.into(),
+                offset: err.offset as u64,
+                line: err.line as u64,
+                column: err.column as u64,
+                token: err.token,
+            },
+        }
+    }
+}
+
+/// One `(output string, path weight)` pair from [UniffiFst::lookup]. [FST::lookup] returns these
+/// as tuples, which uniffi can't pass across the FFI boundary directly.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct LookupResult {
+    pub output: String,
+    pub weight: f64,
+}
+
+/// One entry of a [FlagMap](crate::FlagMap), deinterned to plain strings for the FFI boundary.
+/// `value` is the empty string for a flag diacritic that was parsed without a value (e.g. a bare
+/// `@R.KEY@`); the interned form uses a sentinel index for this that isn't meaningful outside the
+/// Rust engine, so it's normalized away here rather than handed across the FFI boundary.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct UniffiFlagEntry {
+    pub key: String,
+    pub positive: bool,
+    pub value: String,
+}
+
+fn flag_map_to_entries(map: &im::HashMap<u32, (bool, u32)>) -> Vec<UniffiFlagEntry> {
+    map.iter()
+        .map(|(&key, &(positive, value))| UniffiFlagEntry {
+            key: deintern(key),
+            positive,
+            value: if value == u32::MAX {
+                String::new()
+            } else {
+                deintern(value)
+            },
+        })
+        .collect()
+}
+
+/// Kotlin/Swift-visible handle for a loaded transducer. Wraps [FST] behind the `Arc` uniffi
+/// objects are required to be handed out as.
+#[derive(uniffi::Object)]
+pub struct UniffiFst(FST);
+
+#[uniffi::export]
+impl UniffiFst {
+    /// Load a transducer from an AT&T-format file on disk. See [FST::from_att_file].
+    #[uniffi::constructor]
+    pub fn from_att_file(att_file: String, debug: bool) -> Result<Arc<Self>, UniffiError> {
+        FST::from_att_file(att_file, debug)
+            .map(|fst| Arc::new(UniffiFst(fst)))
+            .map_err(UniffiError::from)
+    }
+
+    /// Load a transducer from a KFST binary file on disk. See [FST::from_kfst_file].
+    #[uniffi::constructor]
+    pub fn from_kfst_file(kfst_file: String, debug: bool) -> Result<Arc<Self>, UniffiError> {
+        FST::from_kfst_file(kfst_file, debug)
+            .map(|fst| Arc::new(UniffiFst(fst)))
+            .map_err(UniffiError::from)
+    }
+
+    /// Tokenize and transduce `input`, starting from `state`, returning every analysis found.
+    /// See [FST::lookup].
+    pub fn lookup(
+        &self,
+        input: String,
+        state: Arc<UniffiFstState>,
+        allow_unknown: bool,
+    ) -> Result<Vec<LookupResult>, UniffiError> {
+        self.0
+            .lookup(&input, state.0.clone(), allow_unknown)
+            .map(|rows| {
+                rows.into_iter()
+                    .map(|(output, weight)| LookupResult { output, weight })
+                    .collect()
+            })
+            .map_err(UniffiError::from)
+    }
+}
+
+/// Kotlin/Swift-visible handle for an [FSTState]. Construct one with [UniffiFstState::default] or
+/// [UniffiFstState::new] and thread it through repeated [UniffiFst::lookup] calls.
+#[derive(uniffi::Object)]
+pub struct UniffiFstState(FSTState);
+
+#[uniffi::export]
+impl UniffiFstState {
+    /// The neutral start state: state 0, no weight, no flags set, no output yet.
+    #[uniffi::constructor]
+    pub fn default() -> Arc<Self> {
+        Arc::new(UniffiFstState(FSTState::default()))
+    }
+
+    /// Construct a state from its parts. See [FSTState::new]. A flag entry whose value is the
+    /// empty string is stored as an (interned) empty string rather than "no value" (there is no
+    /// way to express the latter through this constructor, matching the plain Rust `FSTState::new`
+    /// it delegates to).
+    #[uniffi::constructor]
+    pub fn new(
+        state: u64,
+        path_weight: f64,
+        input_flags: Vec<UniffiFlagEntry>,
+        output_flags: Vec<UniffiFlagEntry>,
+        output_symbols: Vec<Arc<UniffiSymbol>>,
+    ) -> Arc<Self> {
+        Arc::new(UniffiFstState(FSTState::__new(
+            state,
+            path_weight,
+            input_flags
+                .into_iter()
+                .map(|entry| (entry.key, (entry.positive, entry.value)))
+                .collect(),
+            output_flags
+                .into_iter()
+                .map(|entry| (entry.key, (entry.positive, entry.value)))
+                .collect(),
+            output_symbols.into_iter().map(|s| s.0.clone()).collect(),
+        )))
+    }
+
+    /// Number of the state in the FST. See [FSTState::state_num].
+    pub fn state_num(&self) -> u64 {
+        self.0.state_num
+    }
+
+    /// Sum of transition weights so far. See [FSTState::path_weight].
+    pub fn path_weight(&self) -> f64 {
+        self.0.path_weight
+    }
+
+    /// The input-side flag state. See [FSTState::input_flags].
+    pub fn input_flags(&self) -> Vec<UniffiFlagEntry> {
+        flag_map_to_entries(&self.0.input_flags.0)
+    }
+
+    /// The output-side flag state. See [FSTState::output_flags].
+    pub fn output_flags(&self) -> Vec<UniffiFlagEntry> {
+        flag_map_to_entries(&self.0.output_flags.0)
+    }
+
+    /// The output symbols produced so far, in their string form. See [FSTState::output_symbols].
+    pub fn output_symbols(&self) -> Vec<String> {
+        self.0
+            .output_symbols
+            .iter()
+            .map(Symbol::get_symbol)
+            .collect()
+    }
+
+    /// Apply a single flag diacritic to this state's input-side flags. See [FSTState::apply_flag].
+    pub fn apply_flag(&self, flag: Arc<UniffiFlagDiacriticSymbol>) -> Option<Arc<Self>> {
+        self.0
+            .apply_flag(&flag.0)
+            .map(|state| Arc::new(UniffiFstState(state)))
+    }
+}
+
+/// Kotlin/Swift-visible handle for a [StringSymbol].
+#[derive(uniffi::Object)]
+pub struct UniffiStringSymbol(StringSymbol);
+
+#[uniffi::export]
+impl UniffiStringSymbol {
+    /// Creates a new string symbol. See [StringSymbol::new].
+    #[uniffi::constructor]
+    pub fn new(string: String, unknown: bool) -> Arc<Self> {
+        Arc::new(UniffiStringSymbol(StringSymbol::new(string, unknown)))
+    }
+
+    /// See [StringSymbol::is_unknown].
+    pub fn is_unknown(&self) -> bool {
+        self.0.is_unknown()
+    }
+
+    /// See [StringSymbol::get_symbol].
+    pub fn get_symbol(&self) -> String {
+        self.0.get_symbol()
+    }
+}
+
+/// Kotlin/Swift-visible handle for a [FlagDiacriticSymbol].
+#[derive(uniffi::Object)]
+pub struct UniffiFlagDiacriticSymbol(FlagDiacriticSymbol);
+
+#[uniffi::export]
+impl UniffiFlagDiacriticSymbol {
+    /// Construct a flag diacritic from a flag type, key and optional value. See
+    /// [FlagDiacriticSymbol::new].
+    #[uniffi::constructor]
+    pub fn new(
+        flag_type: FlagDiacriticType,
+        key: String,
+        value: Option<String>,
+    ) -> Result<Arc<Self>, UniffiError> {
+        FlagDiacriticSymbol::new(format!("{:?}", flag_type), key, value)
+            .map(|symbol| Arc::new(UniffiFlagDiacriticSymbol(symbol)))
+            .map_err(UniffiError::from)
+    }
+
+    /// Deintern the key. See [FlagDiacriticSymbol::key].
+    pub fn key(&self) -> String {
+        self.0.key()
+    }
+
+    /// Deintern the value. See [FlagDiacriticSymbol::value].
+    pub fn value(&self) -> String {
+        self.0.value()
+    }
+}
+
+/// Kotlin/Swift-visible handle for a generic [Symbol]. Exposes the variant-independent operations
+/// directly so callers don't need to downcast; lift a [SpecialSymbol], [UniffiStringSymbol] or
+/// [UniffiFlagDiacriticSymbol] into one with the matching `from_*` constructor, or parse one from
+/// its string form with [UniffiSymbol::parse]. Like [Symbol::parse], this can't produce the
+/// Python-only external variant or [RawSymbol](crate::RawSymbol).
+#[derive(uniffi::Object)]
+pub struct UniffiSymbol(Symbol);
+
+#[uniffi::export]
+impl UniffiSymbol {
+    /// Parse a symbol from its string form (e.g. `@_EPSILON_SYMBOL_@` or a plain string symbol).
+    /// See [crate::from_symbol_string]. Returns `None` only on the empty string.
+    #[uniffi::constructor]
+    pub fn parse(text: String) -> Option<Arc<Self>> {
+        crate::from_symbol_string(&text).map(|symbol| Arc::new(UniffiSymbol(symbol)))
+    }
+
+    /// Wrap a [SpecialSymbol] as a [Symbol]. See [Symbol::Special].
+    #[uniffi::constructor]
+    pub fn from_special(symbol: SpecialSymbol) -> Arc<Self> {
+        Arc::new(UniffiSymbol(Symbol::Special(symbol)))
+    }
+
+    /// Wrap a [UniffiStringSymbol] as a [Symbol]. See [Symbol::String].
+    #[uniffi::constructor]
+    pub fn from_string_symbol(symbol: Arc<UniffiStringSymbol>) -> Arc<Self> {
+        Arc::new(UniffiSymbol(Symbol::String(symbol.0)))
+    }
+
+    /// Wrap a [UniffiFlagDiacriticSymbol] as a [Symbol]. See [Symbol::Flag].
+    #[uniffi::constructor]
+    pub fn from_flag_diacritic(symbol: Arc<UniffiFlagDiacriticSymbol>) -> Arc<Self> {
+        Arc::new(UniffiSymbol(Symbol::Flag(symbol.0)))
+    }
+
+    /// See [Symbol::is_epsilon].
+    pub fn is_epsilon(&self) -> bool {
+        self.0.is_epsilon()
+    }
+
+    /// See [Symbol::is_unknown].
+    pub fn is_unknown(&self) -> bool {
+        self.0.is_unknown()
+    }
+
+    /// See [Symbol::get_symbol]. This is the canonical string form that round-trips through
+    /// [UniffiSymbol::parse] and is the same across every uniffi-generated language binding.
+    pub fn get_symbol(&self) -> String {
+        self.0.get_symbol()
+    }
+}